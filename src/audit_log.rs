@@ -0,0 +1,130 @@
+use crate::clock::{Clock, SystemClock};
+use crate::help_queue::Group;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// An enum of the privileged actions that are audited.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Clear,
+    Import,
+    Drain,
+}
+
+/// A record of a privileged action performed on the help queue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub action: AuditAction,
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    pub groups: Vec<Group>,
+    /// Why the action was taken, if the admin said so.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// An append-only log of the admin actions.
+#[derive(Debug)]
+pub struct AuditLog {
+    entries: RwLock<Vec<AuditEntry>>,
+    clock: Box<dyn Clock>,
+}
+
+impl AuditLog {
+    pub fn new() -> Arc<Self> {
+        Self::with_clock(Box::new(SystemClock))
+    }
+
+    /// Creates an audit log that timestamps its entries with the given clock.
+    pub fn with_clock(clock: Box<dyn Clock>) -> Arc<Self> {
+        Arc::new(Self {
+            entries: RwLock::new(Vec::new()),
+            clock,
+        })
+    }
+
+    /// Appends an entry to the audit log.
+    pub fn record(
+        &self,
+        action: AuditAction,
+        actor: &str,
+        groups: Vec<Group>,
+        reason: Option<String>,
+    ) -> Result<()> {
+        info!(
+            "{} performed {:?} on groups {:?} (reason: {:?})",
+            actor, action, groups, reason
+        );
+        let entry = AuditEntry {
+            action,
+            actor: actor.to_string(),
+            timestamp: self.clock.now(),
+            groups,
+            reason,
+        };
+        match self.entries.write() {
+            Ok(mut entries) => entries.push(entry),
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Returns the audit entries in chronological order.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        match self.entries.read() {
+            Ok(entries) => Ok(entries.clone()),
+            Err(error) => bail!(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod audit_log_tests {
+    use super::*;
+
+    use crate::clock::MockClock;
+
+    use chrono::Duration;
+
+    #[test]
+    fn test01_audit_log_should_be_empty_when_created() {
+        let audit_log = AuditLog::new();
+
+        assert!(audit_log.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test02_entries_are_kept_in_chronological_order() {
+        let clock = MockClock::default();
+        let audit_log = AuditLog::with_clock(Box::new(clock.clone()));
+
+        audit_log
+            .record(AuditAction::Clear, "Ivan", vec![1, 2], None)
+            .expect("Error recording the audit entry");
+        clock.advance(Duration::minutes(5));
+        audit_log
+            .record(
+                AuditAction::Clear,
+                "Ana",
+                vec![],
+                Some("Session ended".to_string()),
+            )
+            .expect("Error recording the audit entry");
+
+        let entries = audit_log.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].actor, "Ivan");
+        assert_eq!(entries[0].groups, vec![1, 2]);
+        assert_eq!(entries[1].actor, "Ana");
+        assert_eq!(entries[1].reason, Some("Session ended".to_string()));
+        assert_eq!(
+            entries[1].timestamp - entries[0].timestamp,
+            Duration::minutes(5)
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+
+/// A source of the current time, so time-based features can be tested
+/// deterministically.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A clock that reads the system's wall-clock time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test
+/// can keep one and hand another to the code under test.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<RwLock<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(RwLock::new(now)),
+        }
+    }
+
+    /// Moves the clock forward by the given amount.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.write().unwrap() += duration;
+    }
+
+    /// Sets the clock to the given time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.write().unwrap() = now;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.read().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use super::*;
+
+    #[test]
+    fn test01_mock_clock_does_not_move_by_itself() {
+        let clock = MockClock::default();
+
+        let before = clock.now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        assert_eq!(clock.now(), before);
+    }
+
+    #[test]
+    fn test02_mock_clock_clones_share_the_same_time() {
+        let clock = MockClock::default();
+        let other_clock = clock.clone();
+        let before = clock.now();
+
+        other_clock.advance(Duration::seconds(30));
+
+        assert_eq!(clock.now(), before + Duration::seconds(30));
+    }
+}
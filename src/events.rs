@@ -0,0 +1,258 @@
+use crate::help_queue::{Group, HelpQueue, Outcome, QueueSnapshot, VoiceChannel};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// How many events are kept for slow subscribers if not configured.
+pub const DEFAULT_EVENT_CAPACITY: usize = 64;
+
+/// A change in the help queue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueueEvent {
+    Enqueued {
+        group: Group,
+        voice_channel: VoiceChannel,
+    },
+    Left {
+        group: Group,
+        outcome: Outcome,
+    },
+    Cleared {
+        reason: Option<String>,
+    },
+    /// A helper claimed the group, which stays queued until it is helped.
+    Claimed {
+        group: Group,
+        helper: String,
+    },
+    /// The group was moved to the back of the queue.
+    Requeued {
+        group: Group,
+    },
+    Restored,
+    /// The group is now in front of the queue and will be helped next.
+    NewFront {
+        group: Group,
+    },
+}
+
+/// What a subscriber receives: either the next event or, if it fell behind
+/// and missed some, the whole queue to start over from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueUpdate {
+    Event(QueueEvent),
+    Resync(QueueSnapshot),
+}
+
+/// A subscription to the changes in a help queue.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: broadcast::Receiver<QueueEvent>,
+    help_queue: Arc<HelpQueue>,
+}
+
+impl Subscription {
+    pub fn new(receiver: broadcast::Receiver<QueueEvent>, help_queue: Arc<HelpQueue>) -> Self {
+        Self {
+            receiver,
+            help_queue,
+        }
+    }
+
+    /// Waits for the next update of the help queue.
+    pub async fn recv(&mut self) -> Result<QueueUpdate> {
+        match self.receiver.recv().await {
+            Ok(event) => Ok(QueueUpdate::Event(event)),
+            Err(RecvError::Lagged(missed)) => {
+                // Skip the backlog, as the snapshot already reflects it.
+                self.receiver = self.receiver.resubscribe();
+                warn!("Subscriber missed {} events, resyncing", missed);
+                Ok(QueueUpdate::Resync(self.help_queue.snapshot()?))
+            }
+            Err(RecvError::Closed) => bail!("The help queue is gone"),
+        }
+    }
+}
+
+/// Announces the front of the help queue every `interval`, so a front that
+/// was held back by `front_debounce` is announced once the window is over.
+/// Failures are logged and retried on the next tick.
+pub async fn announce_front_periodically(help_queue: Arc<HelpQueue>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        if let Err(error) = help_queue.announce_front() {
+            warn!("Error announcing the front of the help queue: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod events_tests {
+    use super::*;
+
+    use crate::clock::{MockClock, SystemClock};
+    use crate::help_queue::HelpQueueConfig;
+
+    #[tokio::test]
+    async fn test01_subscribers_receive_the_changes_in_order() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        let mut subscription = queue.subscribe();
+
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue.dismiss(1).await.expect("Error dismissing help");
+
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::Enqueued {
+                group: 1,
+                voice_channel: 887022804183175188
+            })
+        );
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::NewFront { group: 1 })
+        );
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::Left {
+                group: 1,
+                outcome: Outcome::Dismissed
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test02_lagging_subscribers_resync_from_a_snapshot() {
+        let config = HelpQueueConfig {
+            event_capacity: Some(2),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(SystemClock))
+            .expect("Error creating the help queue");
+        let mut subscription = queue.subscribe();
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let resync = subscription.recv().await.unwrap();
+        queue
+            .enqueue(4, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let next_update = subscription.recv().await.unwrap();
+
+        match resync {
+            QueueUpdate::Resync(snapshot) => assert_eq!(
+                snapshot
+                    .entries
+                    .iter()
+                    .map(|entry| entry.group)
+                    .collect::<Vec<Group>>(),
+                vec![1, 2, 3]
+            ),
+            other => panic!("Expected a resync, got {:?}", other),
+        }
+        assert_eq!(
+            next_update,
+            QueueUpdate::Event(QueueEvent::Enqueued {
+                group: 4,
+                voice_channel: 887022804183175188
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test03_serving_the_front_group_announces_the_new_front() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let mut subscription = queue.subscribe();
+
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::Left {
+                group: 1,
+                outcome: Outcome::Served
+            })
+        );
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::NewFront { group: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test04_clearing_tells_subscribers_why() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        let mut subscription = queue.subscribe();
+
+        queue
+            .clear(Some("Session ended".to_string()))
+            .await
+            .expect("Error clearing the queue");
+
+        assert_eq!(
+            subscription.recv().await.unwrap(),
+            QueueUpdate::Event(QueueEvent::Cleared {
+                reason: Some("Session ended".to_string())
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test05_new_fronts_held_back_by_the_debounce_are_announced_after_it() {
+        let config = HelpQueueConfig {
+            front_debounce: Some(Duration::from_secs(10)),
+            ..HelpQueueConfig::default()
+        };
+        let clock = MockClock::default();
+        let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let mut subscription = queue.subscribe();
+        tokio::spawn(announce_front_periodically(
+            queue.clone(),
+            Duration::from_millis(10),
+        ));
+
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        let left = subscription.recv().await.unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        let new_front = subscription.recv().await.unwrap();
+
+        assert_eq!(
+            left,
+            QueueUpdate::Event(QueueEvent::Left {
+                group: 1,
+                outcome: Outcome::Served
+            })
+        );
+        assert_eq!(
+            new_front,
+            QueueUpdate::Event(QueueEvent::NewFront { group: 2 })
+        );
+    }
+}
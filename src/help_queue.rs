@@ -1,290 +1,3513 @@
+use crate::clock::{Clock, SystemClock};
+use crate::events::{QueueEvent, Subscription, DEFAULT_EVENT_CAPACITY};
+use crate::logger::{Logger, TracingLogger};
+use crate::metrics::Metrics;
+use crate::wal::{self, SnapshotWriter, WalRecord, WriteAheadLog};
+
 use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::info;
 
 /// Shorthand for the group number.
-type Group = u16;
+pub type Group = u16;
 /// Shorthand for discord's voice channel id.
-type VoiceChannel = u64;
+pub type VoiceChannel = u64;
+/// How many groups that left the queue are remembered if not configured.
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+/// How many of the last waits are averaged to estimate waits if not
+/// configured.
+pub const DEFAULT_WAIT_SAMPLES: usize = 20;
+/// How long helping a group is assumed to take before any group is served,
+/// if not configured.
+pub const DEFAULT_HELP_DURATION: Duration = Duration::from_secs(300);
+
+/// The longest helping a group can be assumed to take.
+const MAX_HELP_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Shorthand for a queued group's voice channel, position, enqueue time and
+/// priority.
+type Entry = (VoiceChannel, usize, DateTime<Utc>, Priority);
+
+/// How urgently a group needs help.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    #[default]
+    Normal,
+    /// The group is completely stuck, so it is helped before every `Normal`
+    /// group.
+    High,
+}
+
+/// Settings of the help queue.
+#[derive(Debug, Clone, Default)]
+pub struct HelpQueueConfig {
+    /// How long a group must wait to enqueue again after leaving the queue.
+    pub cooldown: Option<Duration>,
+    /// How many groups can wait in the same voice channel.
+    pub max_groups_per_voice_channel: Option<usize>,
+    /// How many groups can wait in the queue.
+    pub max_queue_size: Option<usize>,
+    /// The only groups that can enqueue (any group if empty).
+    pub allowed_groups: HashSet<Group>,
+    /// Groups that can never enqueue.
+    pub denied_groups: HashSet<Group>,
+    /// How many events are kept for subscribers that fall behind.
+    pub event_capacity: Option<usize>,
+    /// How many groups that left the queue are remembered, dropping the
+    /// oldest ones first.
+    pub history_size: Option<usize>,
+    /// Whether `requeue_to_back` places groups by how long they waited in
+    /// total, instead of always behind every queued group.
+    pub boost_requeues: bool,
+    /// Where to log every change to the queue so it can be recovered after
+    /// a crash (not logged if unset).
+    pub wal_path: Option<PathBuf>,
+    /// Where to save the whole queue after every change, to load it back on
+    /// startup (kept in memory only if unset).
+    pub persistence_path: Option<PathBuf>,
+    /// How many of the last waits are averaged to estimate waits.
+    pub wait_samples: Option<usize>,
+    /// How long helping a group is assumed to take until a group is served.
+    pub default_help_duration: Option<Duration>,
+    /// How far back a group counts as served this session in
+    /// `enqueue_if_not_served` (since the last clear if unset).
+    pub session_window: Option<Duration>,
+    /// The shortest time between two announcements of a new front, so quick
+    /// changes do not spam groups (every new front is announced if unset).
+    pub front_debounce: Option<Duration>,
+}
+
+impl HelpQueueConfig {
+    /// Returns whether the group is allowed to enqueue.
+    pub fn allows(&self, group: Group) -> bool {
+        !self.denied_groups.contains(&group)
+            && (self.allowed_groups.is_empty() || self.allowed_groups.contains(&group))
+    }
+}
+
+/// The error returned when a group is not allowed to enqueue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupNotAllowedError {
+    pub group: Group,
+}
+
+impl fmt::Display for GroupNotAllowedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Group {} is not allowed to enqueue", self.group)
+    }
+}
+
+impl std::error::Error for GroupNotAllowedError {}
+
+/// Why a requester could not be enqueued, or a group could not be taken
+/// out of the queue, as told to clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationCode {
+    ZeroVoiceChannel,
+    VoiceChannelFull,
+    QueueFull,
+    AlreadyQueued,
+    AlreadyServed,
+    RepeatedInBatch,
+    NotAllowed,
+    Cooldown,
+    RateLimited,
+    NotQueued,
+    NotClaimed,
+    EmptyQueue,
+}
+
+/// The state of the help queue that a request ran into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HelpQueueError {
+    AlreadyQueued(Group),
+    NotQueued(Group),
+    /// The group is queued but no helper claimed it.
+    NotClaimed(Group),
+    /// The group was claimed by a helper other than the one completing it.
+    ClaimedByOther(Group),
+    /// No queued group is in the voice channel.
+    NotInVoiceChannel(VoiceChannel),
+    /// The group was never helped, so it cannot be requeued to the back.
+    NeverHelped(Group),
+    HelperAlreadyRegistered(String),
+    HelperNotRegistered(String),
+    /// No helper is registered to take turns.
+    NoHelpers,
+    /// No group is waiting, or every waiting group was skipped.
+    Empty,
+    /// A thread panicked while holding one of the locks of the queue.
+    LockPoisoned,
+}
+
+impl fmt::Display for HelpQueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyQueued(group) => write!(f, "Group {group} already in queue"),
+            Self::NotQueued(group) => write!(f, "Group {group} not in queue"),
+            Self::NotClaimed(group) => write!(f, "Group {group} not claimed by a helper"),
+            Self::ClaimedByOther(group) => write!(f, "Group {group} claimed by another helper"),
+            Self::NotInVoiceChannel(voice_channel) => {
+                write!(f, "No queued group in voice channel {voice_channel}")
+            }
+            Self::NeverHelped(group) => write!(f, "Group {group} was never helped"),
+            Self::HelperAlreadyRegistered(helper) => {
+                write!(f, "Helper {helper} already registered")
+            }
+            Self::HelperNotRegistered(helper) => write!(f, "Helper {helper} not registered"),
+            Self::NoHelpers => write!(f, "No helper registered"),
+            Self::Empty => write!(f, "No group in queue"),
+            Self::LockPoisoned => write!(f, "The help queue is poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for HelpQueueError {}
+
+/// The error returned when a requester cannot be enqueued, naming the field
+/// at fault.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub field: String,
+    pub code: ValidationCode,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(field: &str, code: ValidationCode, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            code,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The error returned when a group enqueues again before its cooldown ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CooldownError {
+    pub group: Group,
+    pub remaining: Duration,
+}
+
+impl fmt::Display for CooldownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Group {} must wait {} seconds before enqueueing again",
+            self.group,
+            self.remaining.as_secs_f64().ceil()
+        )
+    }
+}
+
+impl std::error::Error for CooldownError {}
+
+/// Where a queued group stands in the help queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupStatus {
+    pub group: Group,
+    pub voice_channel: VoiceChannel,
+    /// How many groups will be helped before this one.
+    pub groups_ahead: usize,
+    pub wait_time: Duration,
+}
+
+/// How a group left the help queue.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// A helper took the group with `next`.
+    Served,
+    /// The group left on its own with `dismiss`.
+    Dismissed,
+}
+
+/// A group that left the help queue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryRecord {
+    pub group: Group,
+    pub voice_channel: VoiceChannel,
+    pub outcome: Outcome,
+    /// Who helped the group, if it was served.
+    pub helper: Option<String>,
+    pub wait_secs: u64,
+    pub enqueued_at: DateTime<Utc>,
+    /// When the group got to the front of the queue, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reached_front_at: Option<DateTime<Utc>>,
+    pub left_at: DateTime<Utc>,
+}
+
+/// A queued group as it is exported.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SnapshotEntry {
+    pub group: Group,
+    pub voice_channel: VoiceChannel,
+    pub position: usize,
+    pub enqueued_at: DateTime<Utc>,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+/// A queued group, as shown to helpers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+    pub group: Group,
+    pub voice_channel: VoiceChannel,
+    /// How many groups will be helped before this one.
+    pub position: usize,
+    pub waiting_secs: u64,
+    /// Who is helping the group, if a helper claimed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helper: Option<String>,
+}
+
+/// A point-in-time copy of the help queue, in the order groups are helped.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default, Hash)]
+pub struct QueueSnapshot {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl QueueSnapshot {
+    /// Returns a tag that changes whenever the queue does, as an HTTP
+    /// entity tag.
+    pub fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+
+    /// Checks that the snapshot can be restored into a help queue.
+    pub fn validate(&self) -> Result<()> {
+        let mut groups = HashSet::new();
+        let mut positions = HashSet::new();
+        for entry in &self.entries {
+            if !groups.insert(entry.group) {
+                bail!("Group {} appears more than once", entry.group);
+            }
+            if !positions.insert(entry.position) {
+                bail!("Position {} appears more than once", entry.position);
+            }
+        }
+        Ok(())
+    }
+}
 
 /// The help queue.
+///
+/// Its state lives behind locks, so a single queue is meant to be shared
+/// across tasks through an `Arc<HelpQueue>`, which is what the constructors
+/// return and is already a cheap clone. `new_unwrapped` and `Default` give
+/// the queue itself, for embedding it in another struct.
 #[derive(Debug)]
 pub struct HelpQueue {
-    queue: RwLock<IndexMap<Group, (VoiceChannel, usize)>>,
-    // TODO: Implement logger
-    // logger
+    queue: RwLock<IndexMap<Group, Entry>>,
+    /// When each group last left the queue, for the cooldown.
+    departures: RwLock<HashMap<Group, DateTime<Utc>>>,
+    /// Groups that left the queue, oldest first.
+    history: RwLock<VecDeque<HistoryRecord>>,
+    /// How long each served group waited in total, across all its requests.
+    lifetime_waits: RwLock<HashMap<Group, Duration>>,
+    /// How long the last served groups waited, oldest first.
+    recent_waits: RwLock<VecDeque<Duration>>,
+    /// Helpers taking turns in `next_for_balanced`, along with the turn of
+    /// their last assignment (zero if they were never assigned a group).
+    helpers: RwLock<IndexMap<String, u64>>,
+    config: HelpQueueConfig,
+    clock: Box<dyn Clock>,
+    logger: Box<dyn Logger>,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<QueueEvent>,
+    /// The last group announced as the front of the queue, and when.
+    front: RwLock<(Option<Group>, Option<DateTime<Utc>>)>,
+    /// When each queued group first got to the front of the queue.
+    reached_front: RwLock<HashMap<Group, DateTime<Utc>>>,
+    /// Who claimed each queued group that is being helped.
+    claims: RwLock<HashMap<Group, String>>,
+    /// When the queue was last cleared, or created if it never was.
+    session_start: RwLock<DateTime<Utc>>,
+    /// Where changes are logged before being applied, if configured.
+    wal: Option<WriteAheadLog>,
+    /// What saves the queue after every change, if it is persisted.
+    snapshot_writer: Option<SnapshotWriter>,
 }
 
 impl HelpQueue {
     pub fn new() -> Result<Arc<Self>> {
-        Ok(Arc::new(Self {
-            queue: RwLock::new(IndexMap::new()),
-        }))
+        Self::with_clock(Box::new(SystemClock))
+    }
+
+    /// Creates a help queue that reads the time from the given clock.
+    pub fn with_clock(clock: Box<dyn Clock>) -> Result<Arc<Self>> {
+        Self::with_config(HelpQueueConfig::default(), clock)
+    }
+
+    /// Creates a help queue with the given settings.
+    pub fn with_config(config: HelpQueueConfig, clock: Box<dyn Clock>) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::unwrapped_with_config(config, clock)?))
+    }
+
+    /// Creates a help queue that reports what happens to it to the given
+    /// logger.
+    pub fn with_logger(logger: Box<dyn Logger>) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::unwrapped_with_logger(
+            HelpQueueConfig::default(),
+            Box::new(SystemClock),
+            logger,
+        )?))
+    }
+
+    /// Creates a help queue that is saved to the given path after every
+    /// change, loading the queue saved there if any.
+    pub fn with_persistence(path: impl Into<PathBuf>) -> Result<Arc<Self>> {
+        let config = HelpQueueConfig {
+            persistence_path: Some(path.into()),
+            ..HelpQueueConfig::default()
+        };
+        Self::with_config(config, Box::new(SystemClock))
+    }
+
+    /// Creates a help queue that is not wrapped in an `Arc`.
+    pub fn new_unwrapped() -> Result<Self> {
+        Self::unwrapped_with_config(HelpQueueConfig::default(), Box::new(SystemClock))
+    }
+
+    /// Creates a help queue with the given settings that is not wrapped in
+    /// an `Arc`.
+    pub fn unwrapped_with_config(config: HelpQueueConfig, clock: Box<dyn Clock>) -> Result<Self> {
+        Self::unwrapped_with_logger(config, clock, Box::new(TracingLogger))
+    }
+
+    /// Creates a help queue with the given settings and logger that is not
+    /// wrapped in an `Arc`.
+    pub fn unwrapped_with_logger(
+        config: HelpQueueConfig,
+        clock: Box<dyn Clock>,
+        logger: Box<dyn Logger>,
+    ) -> Result<Self> {
+        let event_capacity = config.event_capacity.unwrap_or(DEFAULT_EVENT_CAPACITY);
+        if event_capacity == 0 {
+            bail!("The event capacity must be positive");
+        }
+        if config.max_queue_size == Some(0) {
+            bail!("The queue size must be positive");
+        }
+        if config.history_size == Some(0) {
+            bail!("The history size must be positive");
+        }
+        if config.wait_samples == Some(0) {
+            bail!("The number of wait samples must be positive");
+        }
+        if config
+            .default_help_duration
+            .is_some_and(|duration| duration > MAX_HELP_DURATION)
+        {
+            bail!("The default help duration cannot be longer than a day");
+        }
+        if config.wal_path.is_some() && config.persistence_path.is_some() {
+            bail!("The help queue can be either logged or persisted, not both");
+        }
+        let (events, _) = broadcast::channel(event_capacity);
+        let (queue, wal, snapshot_writer) = match &config.wal_path {
+            Some(path) => {
+                let (snapshot, records) = WriteAheadLog::recover(path)?;
+                snapshot.validate()?;
+                let mut queue = Self::queue_of(snapshot);
+                for record in records {
+                    Self::replay(&mut queue, record);
+                }
+                info!("Recovered {} groups from {}", queue.len(), path.display());
+                (queue, Some(WriteAheadLog::open(path)?), None)
+            }
+            None => match &config.persistence_path {
+                Some(path) => {
+                    let snapshot = wal::read_snapshot(path)?;
+                    snapshot.validate()?;
+                    let queue = Self::queue_of(snapshot);
+                    info!("Loaded {} groups from {}", queue.len(), path.display());
+                    (queue, None, Some(SnapshotWriter::spawn(path)?))
+                }
+                None => (IndexMap::new(), None, None),
+            },
+        };
+        let session_start = clock.now();
+        Ok(Self {
+            queue: RwLock::new(queue),
+            departures: RwLock::new(HashMap::new()),
+            history: RwLock::new(VecDeque::new()),
+            lifetime_waits: RwLock::new(HashMap::new()),
+            recent_waits: RwLock::new(VecDeque::new()),
+            helpers: RwLock::new(IndexMap::new()),
+            config,
+            clock,
+            logger,
+            metrics: Metrics::new(),
+            events,
+            front: RwLock::new((None, None)),
+            reached_front: RwLock::new(HashMap::new()),
+            claims: RwLock::new(HashMap::new()),
+            session_start: RwLock::new(session_start),
+            wal,
+            snapshot_writer,
+        })
+    }
+
+    /// Pushes a requester to the help queue, returning how many groups will
+    /// be helped before it.
+    pub async fn enqueue(&self, group: Group, voice_channel: VoiceChannel) -> Result<usize> {
+        self.enqueue_with_priority(group, voice_channel, Priority::default())
+            .await
+    }
+
+    /// Pushes a requester to the help queue, ahead of every group of a lower
+    /// priority, returning its position as in `enqueue`.
+    pub async fn enqueue_with_priority(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+        priority: Priority,
+    ) -> Result<usize> {
+        let position = match self.queue.write() {
+            Ok(mut queue) => {
+                // Check before inserting, as `insert` overwrites the entry of a
+                // queued group.
+                if queue.contains_key(&group) {
+                    bail!(HelpQueueError::AlreadyQueued(group));
+                }
+                self.check_enqueue(&queue, group, voice_channel)?;
+                let last_position = Self::next_position(&queue);
+                let now = self.clock.now();
+                self.log(WalRecord::Enqueued {
+                    entries: vec![SnapshotEntry {
+                        group,
+                        voice_channel,
+                        position: last_position,
+                        enqueued_at: now,
+                        priority,
+                    }],
+                })?;
+                let entry = (voice_channel, last_position, now, priority);
+                let position = Self::groups_ahead(&queue, &entry);
+                queue.insert(group, entry);
+                self.persist(&queue);
+                self.metrics.record_enqueues(1);
+                self.logger.log_enqueue(group, voice_channel, priority);
+                self.publish(QueueEvent::Enqueued {
+                    group,
+                    voice_channel,
+                });
+                position
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.announce_front()?;
+        Ok(position)
+    }
+
+    /// Pushes a requester to the help queue unless it was already served this
+    /// session, so each group gets one turn per session.
+    pub async fn enqueue_if_not_served(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+    ) -> Result<usize> {
+        if self.served_this_session(group)? {
+            bail!(ValidationError::new(
+                "group",
+                ValidationCode::AlreadyServed,
+                format!("Group {group} was already helped this session"),
+            ));
+        }
+        self.enqueue(group, voice_channel).await
+    }
+
+    /// Pushes several requesters to the help queue at once, returning their
+    /// positions as in `enqueue`. Either all of them are enqueued or none is.
+    pub async fn enqueue_many(&self, items: Vec<(Group, VoiceChannel)>) -> Result<Vec<usize>> {
+        self.enqueue_many_with_priority(
+            items
+                .into_iter()
+                .map(|(group, voice_channel)| (group, voice_channel, Priority::default()))
+                .collect(),
+        )
+        .await
+    }
+
+    /// Pushes several requesters to the help queue at once as in
+    /// `enqueue_many`, each ahead of every group of a lower priority.
+    pub async fn enqueue_many_with_priority(
+        &self,
+        items: Vec<(Group, VoiceChannel, Priority)>,
+    ) -> Result<Vec<usize>> {
+        let mut batch = HashSet::new();
+        for (group, _, _) in &items {
+            if !batch.insert(*group) {
+                bail!(ValidationError::new(
+                    "group",
+                    ValidationCode::RepeatedInBatch,
+                    format!("Group {group} appears more than once in the batch"),
+                ));
+            }
+        }
+        let positions = match self.queue.write() {
+            Ok(mut queue) => {
+                let mut pending = queue.clone();
+                let first_position = Self::next_position(&queue);
+                let now = self.clock.now();
+                for (offset, (group, voice_channel, priority)) in items.iter().enumerate() {
+                    if queue.contains_key(group) {
+                        bail!(HelpQueueError::AlreadyQueued(*group));
+                    }
+                    self.check_enqueue(&pending, *group, *voice_channel)?;
+                    pending.insert(
+                        *group,
+                        (*voice_channel, first_position + offset, now, *priority),
+                    );
+                }
+                self.log(WalRecord::Enqueued {
+                    entries: items
+                        .iter()
+                        .enumerate()
+                        .map(|(offset, (group, voice_channel, priority))| SnapshotEntry {
+                            group: *group,
+                            voice_channel: *voice_channel,
+                            position: first_position + offset,
+                            enqueued_at: now,
+                            priority: *priority,
+                        })
+                        .collect(),
+                })?;
+                *queue = pending;
+                self.persist(&queue);
+                self.metrics.record_enqueues(items.len() as u64);
+                for (group, voice_channel, priority) in &items {
+                    self.logger.log_enqueue(*group, *voice_channel, *priority);
+                    self.publish(QueueEvent::Enqueued {
+                        group: *group,
+                        voice_channel: *voice_channel,
+                    });
+                }
+                items
+                    .iter()
+                    .map(|(group, _, _)| Self::groups_ahead(&queue, &queue[group]))
+                    .collect()
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.announce_front()?;
+        Ok(positions)
+    }
+
+    /// Puts a group that was helped back in the queue, returning its
+    /// position as in `enqueue`. The cooldown does not apply, as the group is
+    /// coming back with a follow-up question.
+    ///
+    /// It goes behind every queued group unless `boost_requeues` is set, in
+    /// which case it goes ahead of the groups that have been waiting for
+    /// less time than it waited in total, so frequent short questions are
+    /// not starved.
+    pub async fn requeue_to_back(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+    ) -> Result<usize> {
+        let lifetime_wait = match self.lifetime_waits.read() {
+            Ok(lifetime_waits) => match lifetime_waits.get(&group) {
+                Some(lifetime_wait) => *lifetime_wait,
+                None => bail!(HelpQueueError::NeverHelped(group)),
+            },
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        let position = match self.queue.write() {
+            Ok(mut queue) => {
+                if queue.contains_key(&group) {
+                    bail!(HelpQueueError::AlreadyQueued(group));
+                }
+                self.check_requeue(&queue, group, voice_channel)?;
+                let now = self.clock.now();
+                let boosted_position = queue
+                    .values()
+                    .filter(|(_, _, enqueued_at, _)| {
+                        self.config.boost_requeues
+                            && (now - *enqueued_at).to_std().unwrap_or_default() < lifetime_wait
+                    })
+                    .map(|(_, position, _, _)| *position)
+                    .min();
+                let entry = SnapshotEntry {
+                    group,
+                    voice_channel,
+                    position: boosted_position.unwrap_or_else(|| Self::next_position(&queue)),
+                    enqueued_at: now,
+                    priority: Priority::Normal,
+                };
+                self.log(WalRecord::Enqueued {
+                    entries: vec![entry.clone()],
+                })?;
+                Self::insert_at(&mut queue, entry);
+                self.persist(&queue);
+                self.metrics.record_enqueues(1);
+                self.logger
+                    .log_enqueue(group, voice_channel, Priority::Normal);
+                self.publish(QueueEvent::Enqueued {
+                    group,
+                    voice_channel,
+                });
+                Self::groups_ahead(&queue, &queue[&group])
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.announce_front()?;
+        Ok(position)
+    }
+
+    /// Returns the next group in the help queue as a `(group, voice_channel,
+    /// wait)` tuple, where `wait` is how long the group waited to be helped.
+    pub async fn next(&self, helper: String) -> Result<(Group, VoiceChannel, Duration)> {
+        self.next_skipping(helper, &[]).await
+    }
+
+    /// Helps the first group in the help queue that is not in `skip`,
+    /// returning it as in `next`. The skipped groups keep their place.
+    pub async fn next_skipping(
+        &self,
+        helper: String,
+        skip: &[Group],
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        let (group, voice_channel, wait) = self.remove_first(skip).await?;
+        self.serve(group, voice_channel, wait, helper)
+    }
+
+    /// Marks the first group in the help queue that no one claimed as being
+    /// helped by the helper, leaving it queued until it is `complete`d.
+    /// Finding and claiming it happen under the queue's lock, so two helpers
+    /// never claim the same group.
+    pub async fn claim(&self, helper: &str) -> Result<(Group, VoiceChannel)> {
+        let (group, voice_channel) = match self.queue.write() {
+            Ok(queue) => {
+                let mut claims = match self.claims.write() {
+                    Ok(claims) => claims,
+                    Err(_) => bail!(HelpQueueError::LockPoisoned),
+                };
+                let first = queue
+                    .iter()
+                    .filter(|(group, _)| !claims.contains_key(group))
+                    .min_by_key(|(_, entry)| Self::serve_order(entry))
+                    .map(|(group, (voice_channel, _, _, _))| (*group, *voice_channel));
+                match first {
+                    Some((group, voice_channel)) => {
+                        claims.insert(group, helper.to_string());
+                        (group, voice_channel)
+                    }
+                    None => bail!(HelpQueueError::Empty),
+                }
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        info!("{} claimed group {}", helper, group);
+        self.publish(QueueEvent::Claimed {
+            group,
+            helper: helper.to_string(),
+        });
+        Ok((group, voice_channel))
     }
 
-    /// Pushes a requester to the help queue.
-    pub async fn enqueue(&self, group: Group, voice_channel: VoiceChannel) -> Result<()> {
-        println!("Enqueueing group {}", group);
-        let last_position = match self.len() {
-            Ok(position) => position,
-            Err(error) => bail!(error.to_string()),
-        };
-        match self.queue.write() {
-            Ok(mut queue) => match queue.insert(group, (voice_channel, last_position)) {
-                Some(_) => bail!("Group {group} already in queue"),
-                None => Ok(()),
-            },
-            Err(error) => bail!(error.to_string()),
-        }
+    /// Helps a group the helper claimed, removing it from the help queue and
+    /// returning it as in `next`.
+    pub async fn complete(
+        &self,
+        group: Group,
+        helper: &str,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        let claimer = match self.claims.read() {
+            Ok(claims) => claims.get(&group).cloned(),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        match claimer {
+            Some(claimer) if claimer == helper => self.provide_to(group, helper).await,
+            Some(_) => bail!(HelpQueueError::ClaimedByOther(group)),
+            None if self.status(group)?.is_some() => bail!(HelpQueueError::NotClaimed(group)),
+            None => bail!(HelpQueueError::NotQueued(group)),
+        }
+    }
+
+    /// Returns who claimed the group, if anyone did.
+    pub fn claimed_by(&self, group: Group) -> Result<Option<String>> {
+        match self.claims.read() {
+            Ok(claims) => Ok(claims.get(&group).cloned()),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Helps the given group out of order, returning it as in `next`.
+    pub async fn provide_to(
+        &self,
+        group: Group,
+        helper: &str,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        let (group, voice_channel, wait) = self.remove(group, Some(helper)).await?;
+        self.serve(group, voice_channel, wait, helper.to_string())
+    }
+
+    /// Helps every group in the help queue in order, returning them as in
+    /// `next`. They are all removed under the same lock, so no group can
+    /// join or be helped by someone else halfway through. Nothing is removed
+    /// if another helper claimed any of them.
+    pub async fn drain(&self, helper: &str) -> Result<Vec<(Group, VoiceChannel, Duration)>> {
+        info!("{} is draining the help queue", helper);
+        let entries = match self.queue.write() {
+            Ok(mut queue) => {
+                for group in queue.keys() {
+                    self.check_claim(*group, helper)?;
+                }
+                self.log(WalRecord::Cleared)?;
+                let mut entries: Vec<(Group, Entry)> = queue.drain(..).collect();
+                self.persist(&queue);
+                entries.sort_by_key(|(_, entry)| Self::serve_order(entry));
+                entries
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        entries
+            .into_iter()
+            .map(|(group, (voice_channel, _, enqueued_at, _))| {
+                let (group, voice_channel, wait) =
+                    self.depart(group, voice_channel, enqueued_at)?;
+                self.serve(group, voice_channel, wait, helper.to_string())
+            })
+            .collect()
+    }
+
+    /// Takes note of a group that was removed from the queue to be helped.
+    fn serve(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+        wait: Duration,
+        helper: String,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        self.metrics.record_serve(wait)?;
+        match self.lifetime_waits.write() {
+            Ok(mut lifetime_waits) => *lifetime_waits.entry(group).or_default() += wait,
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        let wait_samples = self.config.wait_samples.unwrap_or(DEFAULT_WAIT_SAMPLES);
+        match self.recent_waits.write() {
+            Ok(mut recent_waits) => {
+                if recent_waits.len() >= wait_samples {
+                    recent_waits.pop_front();
+                }
+                recent_waits.push_back(wait);
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        self.logger.log_help(&helper, group, wait);
+        self.record_departure(group, voice_channel, Outcome::Served, Some(helper), wait)?;
+        self.publish(QueueEvent::Left {
+            group,
+            outcome: Outcome::Served,
+        });
+        self.announce_front()?;
+        Ok((group, voice_channel, wait))
+    }
+
+    /// Adds a helper to the ones taking turns in `next_for_balanced`.
+    pub fn register_helper(&self, helper: String) -> Result<()> {
+        match self.helpers.write() {
+            Ok(mut helpers) => {
+                if helpers.contains_key(&helper) {
+                    bail!(HelpQueueError::HelperAlreadyRegistered(helper));
+                }
+                helpers.insert(helper, 0);
+                Ok(())
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Removes a helper from the ones taking turns in `next_for_balanced`.
+    pub fn unregister_helper(&self, helper: &str) -> Result<()> {
+        match self.helpers.write() {
+            Ok(mut helpers) => match helpers.shift_remove(helper) {
+                Some(_) => Ok(()),
+                None => bail!(HelpQueueError::HelperNotRegistered(helper.to_string())),
+            },
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Assigns the next group in the help queue to the registered helper that
+    /// was assigned a group the longest ago, returning the helper along with
+    /// the group as in `next`. The helper's turn is taken under the same lock
+    /// it is picked in, so two calls never pick the same helper; it is given
+    /// back if there is no group to help.
+    pub async fn next_for_balanced(&self) -> Result<(String, Group, VoiceChannel, Duration)> {
+        let (helper, last_turn, turn) = match self.helpers.write() {
+            Ok(mut helpers) => {
+                let turn = helpers.values().max().copied().unwrap_or_default() + 1;
+                match helpers.iter_mut().min_by_key(|(_, turn)| **turn) {
+                    Some((helper, helper_turn)) => {
+                        let last_turn = std::mem::replace(helper_turn, turn);
+                        (helper.clone(), last_turn, turn)
+                    }
+                    None => bail!(HelpQueueError::NoHelpers),
+                }
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        match self.next(helper.clone()).await {
+            Ok((group, voice_channel, wait)) => Ok((helper, group, voice_channel, wait)),
+            Err(error) => {
+                match self.helpers.write() {
+                    Ok(mut helpers) => {
+                        // Unless another call gave the helper a turn since.
+                        if let Some(helper_turn) = helpers
+                            .get_mut(&helper)
+                            .filter(|helper_turn| **helper_turn == turn)
+                        {
+                            *helper_turn = last_turn;
+                        }
+                    }
+                    Err(_) => bail!(HelpQueueError::LockPoisoned),
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Removes the dismisser from the help queue.
+    pub async fn dismiss(&self, dismisser: Group) -> Result<(Group, VoiceChannel, Duration)> {
+        let (group, voice_channel, wait) = self.remove(dismisser, None).await?;
+        self.announce_dismissal(group, voice_channel, wait)
+    }
+
+    /// Records, logs and publishes that a group was dismissed, once it is
+    /// out of the queue.
+    fn announce_dismissal(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+        wait: Duration,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        self.metrics.record_dismissal();
+        self.logger.log_dismiss(group, wait);
+        self.record_departure(group, voice_channel, Outcome::Dismissed, None, wait)?;
+        self.publish(QueueEvent::Left {
+            group,
+            outcome: Outcome::Dismissed,
+        });
+        self.announce_front()?;
+        Ok((group, voice_channel, wait))
+    }
+
+    /// Moves a queued group behind every other group of its priority,
+    /// keeping its voice channel and how long it has waited. Returns how
+    /// many groups are now ahead of it. Its claim, if any, is dropped; a
+    /// helper other than the one who claimed it cannot requeue it.
+    pub async fn requeue(&self, group: Group, helper: Option<&str>) -> Result<usize> {
+        let groups_ahead = match self.queue.write() {
+            Ok(mut queue) => {
+                let (voice_channel, _, enqueued_at, priority) = match queue.get(&group) {
+                    Some(entry) => *entry,
+                    None => bail!(HelpQueueError::NotQueued(group)),
+                };
+                if let Some(helper) = helper {
+                    self.check_claim(group, helper)?;
+                }
+                match self.claims.write() {
+                    Ok(mut claims) => claims.remove(&group),
+                    Err(_) => bail!(HelpQueueError::LockPoisoned),
+                };
+                // Enqueueing a queued group replaces its entry, so the move
+                // is logged as a single record.
+                let entry = SnapshotEntry {
+                    group,
+                    voice_channel,
+                    position: Self::next_position(&queue),
+                    enqueued_at,
+                    priority,
+                };
+                self.log(WalRecord::Enqueued {
+                    entries: vec![entry.clone()],
+                })?;
+                Self::insert_at(&mut queue, entry);
+                self.persist(&queue);
+                info!("Group {} requeued", group);
+                self.publish(QueueEvent::Requeued { group });
+                Self::groups_ahead(&queue, &queue[&group])
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.announce_front()?;
+        Ok(groups_ahead)
+    }
+
+    /// Removes the group in the given voice channel from the help queue, as
+    /// in `dismiss`. If several groups share the channel, the one that would
+    /// be helped first is removed. Finding and removing it happen under the
+    /// same lock, so it cannot be swapped for a group that took its place.
+    ///
+    /// The group is recorded as dismissed, as it left without being helped
+    /// (e.g. its voice channel emptied), even though a helper removed it.
+    pub async fn remove_by_voice_channel(
+        &self,
+        voice_channel: VoiceChannel,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        let (group, (voice_channel, _, enqueued_at, _)) = match self.queue.write() {
+            Ok(mut queue) => {
+                let group = match queue
+                    .iter()
+                    .filter(|(_, (other_voice_channel, _, _, _))| {
+                        *other_voice_channel == voice_channel
+                    })
+                    .min_by_key(|(_, entry)| Self::serve_order(entry))
+                    .map(|(group, _)| *group)
+                {
+                    Some(group) => group,
+                    None => bail!(HelpQueueError::NotInVoiceChannel(voice_channel)),
+                };
+                info!("Removing group {}", group);
+                self.log(WalRecord::Removed { group })?;
+                let entry = match queue.remove_entry(&group) {
+                    Some(entry) => entry,
+                    None => bail!(HelpQueueError::NotInVoiceChannel(voice_channel)),
+                };
+                self.persist(&queue);
+                entry
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        let (group, voice_channel, wait) = self.depart(group, voice_channel, enqueued_at)?;
+        self.announce_dismissal(group, voice_channel, wait)
+    }
+
+    /// Clears the help queue.
+    pub async fn clear(&self, reason: Option<String>) -> Result<()> {
+        self.clear_and_return(reason).await.map(|_| ())
+    }
+
+    /// Clears the help queue as in `clear`, returning the groups that were
+    /// in it in order, so they can be told.
+    pub async fn clear_and_return(
+        &self,
+        reason: Option<String>,
+    ) -> Result<Vec<(Group, VoiceChannel)>> {
+        let cleared = match self.queue.write() {
+            Ok(mut queue) => {
+                self.log(WalRecord::Cleared)?;
+                let mut entries: Vec<(Group, Entry)> = queue.drain(..).collect();
+                self.persist(&queue);
+                entries.sort_by_key(|(_, entry)| Self::serve_order(entry));
+                entries
+                    .into_iter()
+                    .map(|(group, (voice_channel, _, _, _))| (group, voice_channel))
+                    .collect()
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.forget_departed()?;
+        match self.session_start.write() {
+            Ok(mut session_start) => *session_start = self.clock.now(),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        self.logger.log_clear(reason.as_deref());
+        self.publish(QueueEvent::Cleared { reason });
+        self.announce_front()?;
+        Ok(cleared)
+    }
+
+    /// Subscribes to the changes in the help queue.
+    pub fn subscribe(self: &Arc<Self>) -> Subscription {
+        Subscription::new(self.events.subscribe(), self.clone())
+    }
+
+    /// Returns the counters of what happened in the help queue.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Returns the groups that left the queue, oldest first.
+    pub fn history(&self) -> Result<Vec<HistoryRecord>> {
+        match self.history.read() {
+            Ok(history) => Ok(history.iter().cloned().collect()),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns the events involving the group in chronological order: when it
+    /// joined the queue, got to its front and left it, for every request it
+    /// made that is still in the history, plus its current one.
+    pub fn group_timeline(&self, group: Group) -> Result<Vec<(DateTime<Utc>, QueueEvent)>> {
+        let mut timeline = Vec::new();
+        match self.history.read() {
+            Ok(history) => {
+                for record in history.iter().filter(|record| record.group == group) {
+                    timeline.push((
+                        record.enqueued_at,
+                        QueueEvent::Enqueued {
+                            group,
+                            voice_channel: record.voice_channel,
+                        },
+                    ));
+                    if let Some(reached_front_at) = record.reached_front_at {
+                        timeline.push((reached_front_at, QueueEvent::NewFront { group }));
+                    }
+                    timeline.push((
+                        record.left_at,
+                        QueueEvent::Left {
+                            group,
+                            outcome: record.outcome,
+                        },
+                    ));
+                }
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        let queued = match self.queue.read() {
+            Ok(queue) => queue.get(&group).copied(),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        if let Some((voice_channel, _, enqueued_at, _)) = queued {
+            timeline.push((
+                enqueued_at,
+                QueueEvent::Enqueued {
+                    group,
+                    voice_channel,
+                },
+            ));
+            let reached_front_at = match self.reached_front.read() {
+                Ok(reached_front) => reached_front.get(&group).copied(),
+                Err(_) => bail!(HelpQueueError::LockPoisoned),
+            };
+            if let Some(reached_front_at) = reached_front_at {
+                timeline.push((reached_front_at, QueueEvent::NewFront { group }));
+            }
+        }
+        // Stable, so events at the same instant keep their order.
+        timeline.sort_by_key(|(at, _)| *at);
+        Ok(timeline)
+    }
+
+    /// Returns whether the group was ever served, even if it left the queue,
+    /// as long as it is still in the history.
+    pub fn was_served(&self, group: Group) -> Result<bool> {
+        match self.history.read() {
+            Ok(history) => Ok(history
+                .iter()
+                .any(|record| record.group == group && record.outcome == Outcome::Served)),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns whether the group was served within the session window, or
+    /// since the last clear if there is none.
+    pub fn served_this_session(&self, group: Group) -> Result<bool> {
+        let since = match self.config.session_window {
+            Some(window) => self.clock.now() - chrono::Duration::from_std(window)?,
+            None => match self.session_start.read() {
+                Ok(session_start) => *session_start,
+                Err(_) => bail!(HelpQueueError::LockPoisoned),
+            },
+        };
+        match self.history.read() {
+            Ok(history) => Ok(history.iter().any(|record| {
+                record.group == group && record.outcome == Outcome::Served && record.left_at > since
+            })),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns the length of the help queue.
+    pub fn len(&self) -> Result<usize> {
+        match self.queue.read() {
+            Ok(queue) => Ok(queue.len()),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns how many groups can wait in the queue, if it is bounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.config.max_queue_size
+    }
+
+    /// Returns whether no more groups can join the queue.
+    pub fn is_full(&self) -> Result<bool> {
+        Ok(self.remaining()?.is_some_and(|remaining| remaining == 0))
+    }
+
+    /// Returns how many more groups can join the queue, if it is bounded.
+    pub fn remaining(&self) -> Result<Option<usize>> {
+        let len = self.len()?;
+        Ok(self.capacity().map(|capacity| capacity.saturating_sub(len)))
+    }
+
+    /// Returns whether the queue is empty or not.
+    pub fn is_empty(&self) -> Result<bool> {
+        match self.queue.read() {
+            Ok(queue) => Ok(queue.is_empty()),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns the group that will be helped next, without removing it.
+    pub fn peek(&self) -> Result<Option<(Group, VoiceChannel)>> {
+        match self.queue.read() {
+            Ok(queue) => Ok(queue
+                .iter()
+                .min_by_key(|(_, entry)| Self::serve_order(entry))
+                .map(|(group, (voice_channel, _, _, _))| (*group, *voice_channel))),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns the help queue in order.
+    pub fn sorted(&self) -> Result<impl Iterator<Item = Group>> {
+        match self.queue.read() {
+            Ok(queue) => {
+                let aux_queue = queue.clone();
+                let sorted_scores = aux_queue
+                    .sorted_by(|_, entry_1, _, entry_2| {
+                        Self::serve_order(entry_1).cmp(&Self::serve_order(entry_2))
+                    })
+                    .map(|(group, _)| group);
+                Ok(sorted_scores)
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns the queued groups in order, with how long each has waited.
+    pub fn entries(&self) -> Result<Vec<QueueEntry>> {
+        let now = self.clock.now();
+        let snapshot = self.snapshot()?;
+        let claims = match self.claims.read() {
+            Ok(claims) => claims,
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        Ok(snapshot
+            .entries
+            .into_iter()
+            .enumerate()
+            .map(|(position, entry)| QueueEntry {
+                group: entry.group,
+                voice_channel: entry.voice_channel,
+                position,
+                waiting_secs: (now - entry.enqueued_at)
+                    .to_std()
+                    .unwrap_or_default()
+                    .as_secs(),
+                helper: claims.get(&entry.group).cloned(),
+            })
+            .collect())
+    }
+
+    /// Returns a copy of the help queue in order.
+    pub fn snapshot(&self) -> Result<QueueSnapshot> {
+        match self.queue.read() {
+            Ok(queue) => Ok(Self::snapshot_of(&queue)),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Snapshots the help queue into its log, which is emptied as the
+    /// snapshot already has its changes. Does nothing if the queue is not
+    /// logged.
+    pub fn checkpoint(&self) -> Result<()> {
+        let wal = match &self.wal {
+            Some(wal) => wal,
+            None => return Ok(()),
+        };
+        // Hold the write lock so no change is logged in between.
+        match self.queue.write() {
+            Ok(queue) => wal.compact(&Self::snapshot_of(&queue)),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Replaces the help queue with the given snapshot.
+    pub async fn restore(&self, snapshot: QueueSnapshot) -> Result<()> {
+        snapshot.validate()?;
+        info!("Restoring {} groups", snapshot.entries.len());
+        match self.queue.write() {
+            Ok(mut queue) => {
+                self.log(WalRecord::Restored {
+                    snapshot: snapshot.clone(),
+                })?;
+                *queue = Self::queue_of(snapshot);
+                self.persist(&queue);
+                self.publish(QueueEvent::Restored);
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        self.forget_departed()?;
+        self.announce_front()
+    }
+
+    /// Returns how long the group has been waiting in the help queue.
+    pub fn wait_time(&self, group: Group) -> Result<Duration> {
+        match self.queue.read() {
+            Ok(queue) => match queue.get(&group) {
+                Some((_, _, enqueued_at, _)) => Ok((self.clock.now() - *enqueued_at)
+                    .to_std()
+                    .unwrap_or_default()),
+                None => bail!(HelpQueueError::NotQueued(group)),
+            },
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Estimates how long the group will wait from now on to be helped, as
+    /// the groups ahead of it times the average wait of the last served
+    /// groups. A wait too long to be represented is estimated as the longest
+    /// possible one.
+    pub fn estimated_wait(&self, group: Group) -> Result<Duration> {
+        let groups_ahead = match self.status(group)? {
+            Some(status) => status.groups_ahead,
+            None => bail!(HelpQueueError::NotQueued(group)),
+        };
+        let average_wait = match self.recent_waits.read() {
+            Ok(recent_waits) if recent_waits.is_empty() => self
+                .config
+                .default_help_duration
+                .unwrap_or(DEFAULT_HELP_DURATION),
+            Ok(recent_waits) => {
+                let total_wait = recent_waits
+                    .iter()
+                    .try_fold(Duration::ZERO, |total, wait| total.checked_add(*wait))
+                    .unwrap_or(Duration::MAX);
+                total_wait / recent_waits.len() as u32
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        Ok(u32::try_from(groups_ahead)
+            .ok()
+            .and_then(|groups_ahead| average_wait.checked_mul(groups_ahead))
+            .unwrap_or(Duration::MAX))
+    }
+
+    /// Returns where the group stands in the queue, if it is queued.
+    pub fn status(&self, group: Group) -> Result<Option<GroupStatus>> {
+        match self.queue.read() {
+            Ok(queue) => Ok(queue
+                .get(&group)
+                .map(|entry @ (voice_channel, _, enqueued_at, _)| GroupStatus {
+                    group,
+                    voice_channel: *voice_channel,
+                    groups_ahead: Self::groups_ahead(&queue, entry),
+                    wait_time: (self.clock.now() - *enqueued_at)
+                        .to_std()
+                        .unwrap_or_default(),
+                })),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Writes a change to the log before it is applied, if the queue is
+    /// logged.
+    ///
+    /// This blocks on the disk while the caller holds the queue's lock, and
+    /// so does every request waiting on that lock. It is the price of the
+    /// log: a change must be on disk before it is applied and acknowledged,
+    /// and writing under the lock keeps the records in the order the changes
+    /// were applied in.
+    fn log(&self, record: WalRecord) -> Result<()> {
+        match &self.wal {
+            Some(wal) => wal.append(&record),
+            None => Ok(()),
+        }
+    }
+
+    /// Saves the given queue after a change, if the queue is persisted. Only
+    /// the copy is taken under the queue's lock; the write happens on the
+    /// snapshot writer's thread, in the order of the changes.
+    fn persist(&self, queue: &IndexMap<Group, Entry>) {
+        if let Some(snapshot_writer) = &self.snapshot_writer {
+            snapshot_writer.write(Self::snapshot_of(queue));
+        }
+    }
+
+    /// Applies a logged change to the given queue.
+    fn replay(queue: &mut IndexMap<Group, Entry>, record: WalRecord) {
+        match record {
+            WalRecord::Enqueued { entries } => {
+                for entry in entries {
+                    Self::insert_at(queue, entry);
+                }
+            }
+            WalRecord::Removed { group } => {
+                queue.remove(&group);
+            }
+            WalRecord::Cleared => queue.clear(),
+            WalRecord::Restored { snapshot } => *queue = Self::queue_of(snapshot),
+        }
+    }
+
+    /// Inserts a group in the given queue at its position, moving the groups
+    /// at or behind it one position back.
+    fn insert_at(queue: &mut IndexMap<Group, Entry>, entry: SnapshotEntry) {
+        for (_, position, _, _) in queue.values_mut() {
+            if *position >= entry.position {
+                *position += 1;
+            }
+        }
+        queue.insert(
+            entry.group,
+            (
+                entry.voice_channel,
+                entry.position,
+                entry.enqueued_at,
+                entry.priority,
+            ),
+        );
+    }
+
+    /// Returns the entries of the given snapshot, indexed by group.
+    fn queue_of(snapshot: QueueSnapshot) -> IndexMap<Group, Entry> {
+        snapshot
+            .entries
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.group,
+                    (
+                        entry.voice_channel,
+                        entry.position,
+                        entry.enqueued_at,
+                        entry.priority,
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns a copy of the given queue in order.
+    fn snapshot_of(queue: &IndexMap<Group, Entry>) -> QueueSnapshot {
+        let mut entries: Vec<(Group, Entry)> = queue
+            .iter()
+            .map(|(group, entry)| (*group, *entry))
+            .collect();
+        entries.sort_by_key(|(_, entry)| Self::serve_order(entry));
+        QueueSnapshot {
+            entries: entries
+                .into_iter()
+                .map(
+                    |(group, (voice_channel, position, enqueued_at, priority))| SnapshotEntry {
+                        group,
+                        voice_channel,
+                        position,
+                        enqueued_at,
+                        priority,
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    /// Returns the key that sorts queued groups in the order they are
+    /// helped: by priority, and by position within the same priority.
+    fn serve_order((_, position, _, priority): &Entry) -> (Reverse<Priority>, usize) {
+        (Reverse(*priority), *position)
+    }
+
+    /// Returns how many queued groups are helped before the given entry.
+    fn groups_ahead(queue: &IndexMap<Group, Entry>, entry: &Entry) -> usize {
+        queue
+            .values()
+            .filter(|other| Self::serve_order(other) < Self::serve_order(entry))
+            .count()
+    }
+
+    /// Forgets when the groups no longer queued got to the front of the
+    /// queue and who claimed them, for when they leave it without being
+    /// recorded in the history.
+    fn forget_departed(&self) -> Result<()> {
+        let queue = match self.queue.read() {
+            Ok(queue) => queue,
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        match self.reached_front.write() {
+            Ok(mut reached_front) => reached_front.retain(|group, _| queue.contains_key(group)),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        match self.claims.write() {
+            Ok(mut claims) => claims.retain(|group, _| queue.contains_key(group)),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        Ok(())
+    }
+
+    /// Notifies the subscribers of a change, if there are any.
+    fn publish(&self, event: QueueEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Announces the group in front of the queue if it changed since the last
+    /// announcement, so each group is told it is next only once. Within
+    /// `front_debounce` of the last announcement nothing is announced; the
+    /// front is then announced by the first call after it, so it must also
+    /// be called periodically (see `announce_front_periodically`) for a
+    /// front that arrived within the window to be told even if the queue
+    /// does not change again.
+    pub fn announce_front(&self) -> Result<()> {
+        let mut front_lock = match self.front.write() {
+            Ok(front_lock) => front_lock,
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        let (announced_front, announced_at) = &mut *front_lock;
+        let front = match self.queue.read() {
+            Ok(queue) => queue
+                .iter()
+                .min_by_key(|(_, entry)| Self::serve_order(entry))
+                .map(|(group, _)| *group),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        if front == *announced_front {
+            return Ok(());
+        }
+        let group = match front {
+            Some(group) => group,
+            None => {
+                *announced_front = None;
+                return Ok(());
+            }
+        };
+        let now = self.clock.now();
+        match self.reached_front.write() {
+            Ok(mut reached_front) => {
+                reached_front.entry(group).or_insert(now);
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        let debounced = match (self.config.front_debounce, *announced_at) {
+            (Some(debounce), Some(announced_at)) => {
+                (now - announced_at).to_std().unwrap_or_default() < debounce
+            }
+            _ => false,
+        };
+        if !debounced {
+            *announced_front = front;
+            *announced_at = Some(now);
+            self.publish(QueueEvent::NewFront { group });
+        }
+        Ok(())
+    }
+
+    /// Adds a group that left the queue to the history.
+    fn record_departure(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+        outcome: Outcome,
+        helper: Option<String>,
+        wait: Duration,
+    ) -> Result<()> {
+        let history_size = self.config.history_size.unwrap_or(DEFAULT_HISTORY_SIZE);
+        let reached_front_at = match self.reached_front.write() {
+            Ok(mut reached_front) => reached_front.remove(&group),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        let left_at = self.clock.now();
+        match self.history.write() {
+            Ok(mut history) => {
+                if history.len() >= history_size {
+                    history.pop_front();
+                }
+                history.push_back(HistoryRecord {
+                    group,
+                    voice_channel,
+                    outcome,
+                    helper,
+                    wait_secs: wait.as_secs(),
+                    enqueued_at: left_at - chrono::Duration::from_std(wait).unwrap_or_default(),
+                    reached_front_at,
+                    left_at,
+                });
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+        Ok(())
+    }
+
+    /// Checks whether the group is allowed to join the given queue.
+    fn check_enqueue(
+        &self,
+        queue: &IndexMap<Group, Entry>,
+        group: Group,
+        voice_channel: VoiceChannel,
+    ) -> Result<()> {
+        self.check_requeue(queue, group, voice_channel)?;
+        if let Some(remaining) = self.cooldown_remaining(group)? {
+            bail!(CooldownError { group, remaining });
+        }
+        Ok(())
+    }
+
+    /// Checks whether the group is allowed to join the given queue again,
+    /// which is as in `check_enqueue` but ignoring the cooldown.
+    fn check_requeue(
+        &self,
+        queue: &IndexMap<Group, Entry>,
+        group: Group,
+        voice_channel: VoiceChannel,
+    ) -> Result<()> {
+        if voice_channel == 0 {
+            bail!(ValidationError::new(
+                "voice_channel",
+                ValidationCode::ZeroVoiceChannel,
+                "Voice channel 0 is not a valid discord id".to_string(),
+            ));
+        }
+        if !self.config.allows(group) {
+            bail!(GroupNotAllowedError { group });
+        }
+        if let Some(max_queue_size) = self.config.max_queue_size {
+            if queue.len() >= max_queue_size {
+                bail!(ValidationError::new(
+                    "group",
+                    ValidationCode::QueueFull,
+                    format!("The queue already has {max_queue_size} groups waiting"),
+                ));
+            }
+        }
+        if let Some(max_groups) = self.config.max_groups_per_voice_channel {
+            let groups_in_voice_channel = queue
+                .iter()
+                .filter(|(other_group, (other_voice_channel, _, _, _))| {
+                    **other_group != group && *other_voice_channel == voice_channel
+                })
+                .count();
+            if groups_in_voice_channel >= max_groups {
+                bail!(ValidationError::new(
+                    "voice_channel",
+                    ValidationCode::VoiceChannelFull,
+                    format!(
+                        "Voice channel {voice_channel} already has {max_groups} groups waiting"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the position for the next group to join the given queue,
+    /// which is behind every queued group.
+    fn next_position(queue: &IndexMap<Group, Entry>) -> usize {
+        queue
+            .values()
+            .map(|(_, position, _, _)| position + 1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Checks that the group is not claimed by a helper other than the given
+    /// one.
+    fn check_claim(&self, group: Group, helper: &str) -> Result<()> {
+        match self.claims.read() {
+            Ok(claims) => match claims.get(&group) {
+                Some(claimer) if claimer != helper => {
+                    bail!(HelpQueueError::ClaimedByOther(group))
+                }
+                _ => Ok(()),
+            },
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Returns how long the group must still wait to enqueue again, if at all.
+    fn cooldown_remaining(&self, group: Group) -> Result<Option<Duration>> {
+        let cooldown = match self.config.cooldown {
+            Some(cooldown) => cooldown,
+            None => return Ok(None),
+        };
+        match self.departures.read() {
+            Ok(departures) => Ok(departures.get(&group).and_then(|departure| {
+                let elapsed = (self.clock.now() - *departure).to_std().unwrap_or_default();
+                cooldown
+                    .checked_sub(elapsed)
+                    .filter(|remaining| !remaining.is_zero())
+            })),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        }
+    }
+
+    /// Removes a group from the help queue, returning how long it waited.
+    /// A group removed to be helped must not be claimed by another helper.
+    async fn remove(
+        &self,
+        group: Group,
+        helper: Option<&str>,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        info!("Removing group {}", group);
+        let (voice_channel, _, enqueued_at, _) = match self.queue.write() {
+            Ok(mut queue) => {
+                if !queue.contains_key(&group) {
+                    bail!(HelpQueueError::NotQueued(group));
+                }
+                if let Some(helper) = helper {
+                    self.check_claim(group, helper)?;
+                }
+                self.log(WalRecord::Removed { group })?;
+                let entry = match queue.remove(&group) {
+                    Some(entry) => entry,
+                    None => bail!(HelpQueueError::NotQueued(group)),
+                };
+                self.persist(&queue);
+                entry
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        self.depart(group, voice_channel, enqueued_at)
+    }
+
+    /// Removes the first group in the help queue that is not in `skip` nor
+    /// claimed, returning how long it waited. Finding and removing it happen
+    /// under the same lock, so two helpers never take the same group.
+    async fn remove_first(&self, skip: &[Group]) -> Result<(Group, VoiceChannel, Duration)> {
+        let (group, (voice_channel, _, enqueued_at, _)) = match self.queue.write() {
+            Ok(mut queue) => {
+                let claims = match self.claims.read() {
+                    Ok(claims) => claims,
+                    Err(_) => bail!(HelpQueueError::LockPoisoned),
+                };
+                let first = queue
+                    .iter()
+                    .filter(|(group, _)| !skip.contains(group) && !claims.contains_key(group))
+                    .min_by_key(|(_, entry)| Self::serve_order(entry))
+                    .map(|(group, _)| *group);
+                if let Some(group) = first {
+                    self.log(WalRecord::Removed { group })?;
+                }
+                let entry = match first.and_then(|group| queue.remove_entry(&group)) {
+                    Some(entry) => entry,
+                    None => bail!(HelpQueueError::Empty),
+                };
+                self.persist(&queue);
+                entry
+            }
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        info!("Removing group {}", group);
+        self.depart(group, voice_channel, enqueued_at)
+    }
+
+    /// Takes note of a group that left the queue, returning how long it
+    /// waited.
+    fn depart(
+        &self,
+        group: Group,
+        voice_channel: VoiceChannel,
+        enqueued_at: DateTime<Utc>,
+    ) -> Result<(Group, VoiceChannel, Duration)> {
+        let wait = (self.clock.now() - enqueued_at)
+            .to_std()
+            .unwrap_or_default();
+        match self.claims.write() {
+            Ok(mut claims) => claims.remove(&group),
+            Err(_) => bail!(HelpQueueError::LockPoisoned),
+        };
+        if self.config.cooldown.is_some() {
+            match self.departures.write() {
+                Ok(mut departures) => departures.insert(group, self.clock.now()),
+                Err(_) => bail!(HelpQueueError::LockPoisoned),
+            };
+        }
+        Ok((group, voice_channel, wait))
+    }
+}
+
+impl Default for HelpQueue {
+    fn default() -> Self {
+        Self::new_unwrapped().expect("The default settings are valid")
+    }
+}
+
+// TODO: Solve 'Cannot start a runtime from within a runtime. This happens
+// because a function (like `block_on`) attempted to block the current thread
+// while the thread is being used to drive asynchronous tasks.'
+#[cfg(test)]
+mod help_queue_tests {
+    use super::*;
+
+    use crate::clock::MockClock;
+    use crate::logger::{LogEntry, MockLogger};
+
+    #[test]
+    fn test01_help_queue_should_be_empty_when_created() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        assert!(queue.is_empty().is_ok());
+        assert!(queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test02_help_queue_should_not_be_empty_after_enqueueing() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error creating the help queue");
+
+        assert!(queue.len().is_ok());
+        assert_eq!(queue.len().unwrap(), 1);
+        assert!(queue.is_empty().is_ok());
+        assert!(!queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test03_next_in_queue_should_be_the_last_enqueued() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.next("Ivan".to_string()).await;
+
+        if let Ok((group, voice_channel, _)) = expected_result {
+            assert_eq!(queue.len().unwrap(), 0);
+            assert_eq!(group, 1);
+            assert_eq!(voice_channel, 887022804183175188);
+        }
+    }
+
+    #[tokio::test]
+    async fn test04_more_than_one_group_can_request_for_help() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+
+        assert_eq!(queue.len().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test05_queue_behaves_fifo() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.next("Ivan".to_string()).await;
+        let other_expected_result = queue.next("Ivan".to_string()).await;
+
+        assert_eq!(queue.len().unwrap(), 0);
+        if let Ok((group, voice_channel, _)) = expected_result {
+            assert_eq!(group, 1);
+            assert_eq!(voice_channel, 887022804183175188);
+        }
+        if let Ok((group, voice_channel, _)) = other_expected_result {
+            assert_eq!(group, 2);
+            assert_eq!(voice_channel, 887022804183175189);
+        }
+    }
+
+    #[tokio::test]
+    async fn test06_cannot_enqueue_the_same_group_twice() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.enqueue(1, 887022804183175189).await;
+
+        assert_eq!(queue.len().unwrap(), 1);
+        assert!(expected_result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test07_there_is_no_next_in_an_empty_queue() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        let expected_result = queue.next("Ivan".to_string()).await;
+
+        assert!(expected_result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test08_queue_is_empty_after_clearing() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.clear(None).await;
+
+        assert_eq!(queue.len().unwrap(), 0);
+        assert!(expected_result.is_ok());
+        assert!(queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test09_requesters_can_dismiss_their_request() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.dismiss(1).await;
+
+        assert_eq!(queue.len().unwrap(), 0);
+        assert!(expected_result.is_ok());
+        let (group, voice_channel, _) = expected_result.unwrap();
+        assert_eq!((group, voice_channel), (1, 887022804183175188));
+    }
+
+    #[tokio::test]
+    async fn test10_requesters_cannot_dismiss_if_they_did_not_request_for_help() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        let expected_result = queue.dismiss(2).await;
+
+        assert!(expected_result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test11_groups_that_requested_for_help_can_be_retrieved_sorted() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(3, 887022804183175190)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue.sorted();
+
+        assert!(expected_result.is_ok());
+        assert_eq!(
+            expected_result.unwrap().collect::<Vec<u16>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test12_wait_time_grows_as_the_clock_advances() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        assert_eq!(queue.wait_time(1).unwrap(), Duration::ZERO);
+
+        clock.advance(chrono::Duration::minutes(3));
+
+        assert_eq!(queue.wait_time(1).unwrap(), Duration::from_secs(180));
+    }
+
+    #[tokio::test]
+    async fn test13_there_is_no_wait_time_for_groups_not_in_queue() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        assert!(queue.wait_time(1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test14_snapshot_round_trips_through_restore() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+        let snapshot = queue.snapshot().expect("Error taking the snapshot");
+
+        let other_queue = HelpQueue::new().expect("Error creating the help queue");
+        let expected_result = other_queue.restore(snapshot.clone()).await;
+
+        assert!(expected_result.is_ok());
+        assert_eq!(other_queue.snapshot().unwrap(), snapshot);
+        assert_eq!(
+            other_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test15_snapshots_with_repeated_groups_cannot_be_restored() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let entry = SnapshotEntry {
+            group: 2,
+            voice_channel: 887022804183175189,
+            position: 0,
+            enqueued_at: Utc::now(),
+            priority: Priority::Normal,
+        };
+        let snapshot = QueueSnapshot {
+            entries: vec![
+                entry.clone(),
+                SnapshotEntry {
+                    position: 1,
+                    ..entry
+                },
+            ],
+        };
+
+        let expected_result = queue.restore(snapshot).await;
+
+        assert!(expected_result.is_err());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test16_groups_cannot_enqueue_again_during_their_cooldown() {
+        let clock = MockClock::default();
+        let config = HelpQueueConfig {
+            cooldown: Some(Duration::from_secs(60)),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error getting the next group");
+
+        clock.advance(chrono::Duration::seconds(20));
+        let expected_result = queue.enqueue(1, 887022804183175188).await;
+
+        assert!(expected_result.is_err());
+        assert_eq!(
+            expected_result
+                .unwrap_err()
+                .downcast_ref::<CooldownError>()
+                .expect("Expected a cooldown error"),
+            &CooldownError {
+                group: 1,
+                remaining: Duration::from_secs(40),
+            }
+        );
+        assert!(queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test17_groups_can_enqueue_again_after_their_cooldown() {
+        let clock = MockClock::default();
+        let config = HelpQueueConfig {
+            cooldown: Some(Duration::from_secs(60)),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue.dismiss(1).await.expect("Error dismissing help");
+
+        clock.advance(chrono::Duration::seconds(60));
+        let expected_result = queue.enqueue(1, 887022804183175188).await;
+
+        assert!(expected_result.is_ok());
+        assert_eq!(queue.len().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test18_next_reports_how_long_the_group_waited() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+
+        let (_, _, first_wait) = queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error getting the next group");
+        clock.advance(chrono::Duration::seconds(90));
+        let (_, _, second_wait) = queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error getting the next group");
+
+        assert_eq!(first_wait, Duration::ZERO);
+        assert_eq!(second_wait, Duration::from_secs(90));
+    }
+
+    #[tokio::test]
+    async fn test19_voice_channels_cannot_exceed_their_capacity() {
+        let config = HelpQueueConfig {
+            max_groups_per_voice_channel: Some(2),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(SystemClock))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let full_channel_result = queue.enqueue(3, 887022804183175188).await;
+        let other_channel_result = queue.enqueue(3, 887022804183175189).await;
+
+        assert!(full_channel_result.is_err());
+        assert!(other_channel_result.is_ok());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test20_many_groups_can_be_enqueued_at_once() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let expected_result = queue
+            .enqueue_many(vec![(2, 887022804183175189), (3, 887022804183175190)])
+            .await;
+
+        assert!(expected_result.is_ok());
+        assert_eq!(expected_result.unwrap(), vec![1, 2]);
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test21_batches_with_repeated_groups_are_not_enqueued() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let repeated_in_batch = queue
+            .enqueue_many(vec![(2, 887022804183175189), (2, 887022804183175190)])
+            .await;
+        let already_in_queue = queue
+            .enqueue_many(vec![(3, 887022804183175189), (1, 887022804183175190)])
+            .await;
+
+        assert!(repeated_in_batch.is_err());
+        assert!(already_in_queue.is_err());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test22_denied_groups_cannot_enqueue() {
+        let config = HelpQueueConfig {
+            denied_groups: HashSet::from([0]),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+
+        let denied_result = queue.enqueue(0, 887022804183175188).await;
+        let allowed_result = queue.enqueue(1, 887022804183175189).await;
+
+        assert_eq!(
+            denied_result
+                .unwrap_err()
+                .downcast::<GroupNotAllowedError>()
+                .unwrap(),
+            GroupNotAllowedError { group: 0 }
+        );
+        assert!(allowed_result.is_ok());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test23_only_allowed_groups_can_enqueue_when_there_are_any() {
+        let config = HelpQueueConfig {
+            allowed_groups: HashSet::from([1, 2]),
+            denied_groups: HashSet::from([2]),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+
+        let allowed_result = queue.enqueue(1, 887022804183175188).await;
+        let denied_result = queue.enqueue(2, 887022804183175189).await;
+        let absent_result = queue.enqueue(3, 887022804183175190).await;
+
+        assert!(allowed_result.is_ok());
+        assert!(denied_result
+            .unwrap_err()
+            .downcast_ref::<GroupNotAllowedError>()
+            .is_some());
+        assert!(absent_result
+            .unwrap_err()
+            .downcast_ref::<GroupNotAllowedError>()
+            .is_some());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test24_the_status_of_a_group_says_where_it_stands() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(30));
+
+        let expected_result = queue.status(2);
+
+        assert_eq!(
+            expected_result.unwrap(),
+            Some(GroupStatus {
+                group: 2,
+                voice_channel: 887022804183175189,
+                groups_ahead: 1,
+                wait_time: Duration::from_secs(30),
+            })
+        );
+        assert_eq!(queue.status(3).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test25_served_groups_are_recorded_as_served() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+
+        let history = queue.history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].group, 1);
+        assert_eq!(history[0].outcome, Outcome::Served);
+        assert_eq!(history[0].helper, Some("Ivan".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test26_dismissed_groups_are_recorded_as_dismissed() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        queue.dismiss(1).await.expect("Error dismissing");
+
+        let history = queue.history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].group, 1);
+        assert_eq!(history[0].outcome, Outcome::Dismissed);
+        assert_eq!(history[0].helper, None);
+    }
+
+    #[tokio::test]
+    async fn test27_balanced_next_assigns_groups_to_helpers_in_turns() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=5 {
+            queue
+                .enqueue(group, 887022804183175188 + group as u64)
+                .await
+                .expect("Error enqueueing help");
+        }
+        for helper in ["Ivan", "Lucia", "Mateo"] {
+            queue
+                .register_helper(helper.to_string())
+                .expect("Error registering helper");
+        }
+
+        let mut assignments = Vec::new();
+        for _ in 1..=5 {
+            let (helper, group, _, _) = queue.next_for_balanced().await.expect("Error helping");
+            assignments.push((helper, group));
+        }
+
+        assert_eq!(
+            assignments,
+            vec![
+                ("Ivan".to_string(), 1),
+                ("Lucia".to_string(), 2),
+                ("Mateo".to_string(), 3),
+                ("Ivan".to_string(), 4),
+                ("Lucia".to_string(), 5),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test28_unregistered_helpers_are_not_assigned_groups() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .register_helper("Ivan".to_string())
+            .expect("Error registering helper");
+        queue
+            .register_helper("Lucia".to_string())
+            .expect("Error registering helper");
+
+        queue
+            .unregister_helper("Ivan")
+            .expect("Error unregistering helper");
+        let (helper, group, _, _) = queue.next_for_balanced().await.expect("Error helping");
+
+        assert_eq!((helper.as_str(), group), ("Lucia", 1));
+        assert!(queue.unregister_helper("Ivan").is_err());
+    }
+
+    #[tokio::test]
+    async fn test29_groups_cannot_wait_in_voice_channel_zero() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        let zero_result = queue.enqueue(1, 0).await;
+        let real_result = queue.enqueue(2, 887022804183175188).await;
+
+        assert!(zero_result.is_err());
+        assert!(real_result.is_ok());
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test30_dismissing_reports_how_long_the_group_waited() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(420));
+
+        let expected_result = queue.dismiss(1).await;
+
+        assert_eq!(
+            expected_result.unwrap(),
+            (1, 887022804183175188, Duration::from_secs(420))
+        );
+        assert_eq!(queue.history().unwrap()[0].wait_secs, 420);
+    }
+
+    #[test]
+    fn test31_default_help_queue_is_empty() {
+        let queue = HelpQueue::default();
+
+        assert!(queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test32_a_default_help_queue_can_be_shared_across_tasks() {
+        let queue = Arc::new(HelpQueue::default());
+
+        let tasks: Vec<_> = (1..=2)
+            .map(|group| {
+                let queue = queue.clone();
+                tokio::spawn(async move { queue.enqueue(group, 887022804183175188).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().expect("Error enqueueing help");
+        }
+
+        let mut groups: Vec<u16> = queue.sorted().unwrap().collect();
+        groups.sort();
+        assert_eq!(groups, vec![1, 2]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test33_concurrent_operations_keep_the_queue_consistent() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        let groups: u16 = 200;
+        let barrier = Arc::new(tokio::sync::Barrier::new(groups as usize));
+
+        let tasks: Vec<_> = (1..=groups)
+            .map(|group| {
+                let queue = queue.clone();
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    queue
+                        .enqueue(group, 887022804183175188 + group as u64 % 7)
+                        .await
+                        .expect("Error enqueueing help");
+                    match group % 3 {
+                        0 => queue.dismiss(group).await.is_ok(),
+                        1 => queue.next(format!("Helper {group}")).await.is_ok(),
+                        _ => false,
+                    }
+                })
+            })
+            .collect();
+        let mut departures = 0;
+        for task in tasks {
+            if task.await.expect("A task panicked") {
+                departures += 1;
+            }
+        }
+
+        let snapshot = queue.snapshot().unwrap();
+        assert!(snapshot.validate().is_ok());
+        assert_eq!(queue.len().unwrap(), groups as usize - departures);
+        assert_eq!(queue.history().unwrap().len(), departures);
+        // Every helper is given a group, as there is always one waiting.
+        assert_eq!(
+            queue
+                .history()
+                .unwrap()
+                .iter()
+                .filter(|record| record.outcome == Outcome::Served)
+                .count(),
+            (1..=groups).filter(|group| group % 3 == 1).count()
+        );
+    }
+
+    #[tokio::test]
+    async fn test34_groups_enqueued_after_a_departure_go_last() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        queue.dismiss(1).await.expect("Error dismissing help");
+        queue
+            .enqueue(4, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![2, 3, 4]);
+        assert!(queue.snapshot().unwrap().validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test35_served_groups_are_known_after_leaving_the_queue() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        queue.dismiss(2).await.expect("Error dismissing help");
+
+        assert!(queue.was_served(1).unwrap());
+        assert!(!queue.was_served(2).unwrap());
+        assert!(!queue.was_served(3).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test36_the_etag_changes_with_the_queue() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        let empty_etag = queue.snapshot().unwrap().etag();
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let etag = queue.snapshot().unwrap().etag();
+
+        assert_ne!(empty_etag, etag);
+        assert_eq!(queue.snapshot().unwrap().etag(), etag);
+    }
+
+    #[tokio::test]
+    async fn test37_a_group_can_be_helped_out_of_order() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188 + group as u64)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let expected_result = queue.provide_to(2, "Ivan").await;
+
+        let (group, voice_channel, _) = expected_result.unwrap();
+        assert_eq!((group, voice_channel), (2, 887022804183175190));
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1, 3]);
+        assert!(queue.was_served(2).unwrap());
+        assert!(queue.provide_to(2, "Ivan").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test38_the_history_drops_its_oldest_records_when_full() {
+        let config = HelpQueueConfig {
+            history_size: Some(2),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+            queue.next("Ivan".to_string()).await.expect("Error helping");
+        }
+
+        let history = queue.history().unwrap();
+
+        assert_eq!(
+            history
+                .iter()
+                .map(|record| record.group)
+                .collect::<Vec<u16>>(),
+            vec![2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test39_invalid_enqueues_name_the_field_at_fault() {
+        let config = HelpQueueConfig {
+            max_groups_per_voice_channel: Some(1),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let cases = [
+            (
+                queue.enqueue(2, 0).await,
+                "voice_channel",
+                ValidationCode::ZeroVoiceChannel,
+            ),
+            (
+                queue.enqueue(2, 887022804183175188).await,
+                "voice_channel",
+                ValidationCode::VoiceChannelFull,
+            ),
+        ];
+
+        for (result, field, code) in cases {
+            let error = result.unwrap_err().downcast::<ValidationError>().unwrap();
+            assert_eq!((error.field.as_str(), error.code), (field, code));
+        }
+    }
+
+    #[tokio::test]
+    async fn test40_boosted_requeues_count_the_total_wait_of_the_group() {
+        let mut positions = Vec::new();
+        let mut orders = Vec::new();
+        for boost_requeues in [false, true] {
+            let config = HelpQueueConfig {
+                boost_requeues,
+                ..HelpQueueConfig::default()
+            };
+            let clock = MockClock::default();
+            let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+                .expect("Error creating the help queue");
+            for group in [1, 2] {
+                queue
+                    .enqueue(group, 887022804183175188)
+                    .await
+                    .expect("Error enqueueing help");
+            }
+            clock.advance(chrono::Duration::minutes(10));
+            queue.next("Ivan".to_string()).await.expect("Error helping");
+            clock.advance(chrono::Duration::minutes(5));
+            queue
+                .enqueue(3, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+            clock.advance(chrono::Duration::minutes(1));
+
+            let position = queue
+                .requeue_to_back(1, 887022804183175188)
+                .await
+                .expect("Error requeueing help");
+
+            positions.push(position);
+            orders.push(queue.sorted().unwrap().collect::<Vec<Group>>());
+        }
+
+        assert_eq!(positions, vec![2, 1]);
+        assert_eq!(orders, vec![vec![2, 3, 1], vec![2, 1, 3]]);
+    }
+
+    #[tokio::test]
+    async fn test41_bounded_queues_are_full_at_their_capacity() {
+        let config = HelpQueueConfig {
+            max_queue_size: Some(2),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let remaining_before_full = queue.remaining().unwrap();
+        queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+
+        let error = queue
+            .enqueue(3, 887022804183175190)
+            .await
+            .unwrap_err()
+            .downcast::<ValidationError>()
+            .unwrap();
+
+        assert_eq!(queue.capacity(), Some(2));
+        assert_eq!(remaining_before_full, Some(1));
+        assert_eq!(queue.remaining().unwrap(), Some(0));
+        assert!(queue.is_full().unwrap());
+        assert_eq!(error.code, ValidationCode::QueueFull);
+        assert_eq!(queue.len().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test42_unbounded_queues_are_never_full() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=100 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        assert_eq!(queue.capacity(), None);
+        assert_eq!(queue.remaining().unwrap(), None);
+        assert!(!queue.is_full().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test43_enqueueing_a_queued_group_leaves_its_entry_untouched() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        for group in [1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let before = queue.snapshot().unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+
+        let result = queue.enqueue(1, 887022804183175189).await;
+
+        assert!(result.is_err());
+        assert_eq!(queue.snapshot().unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn test44_skipped_groups_keep_their_place() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let (group, _, _) = queue
+            .next_skipping("Ivan".to_string(), &[1])
+            .await
+            .expect("Error helping");
+
+        assert_eq!(group, 2);
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<Group>>(), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test45_draining_serves_every_group_in_order() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [3, 1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let drained = queue.drain("Ivan").await.expect("Error draining the queue");
+
+        assert_eq!(
+            drained
+                .iter()
+                .map(|(group, _, _)| *group)
+                .collect::<Vec<Group>>(),
+            vec![3, 1, 2]
+        );
+        assert!(queue.is_empty().unwrap());
+        assert!(queue
+            .history()
+            .unwrap()
+            .iter()
+            .all(|record| record.outcome == Outcome::Served));
+    }
+
+    #[tokio::test]
+    async fn test46_a_logged_queue_is_recovered_exactly() {
+        let path = std::env::temp_dir().join(format!("algo3-{}-test46.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(WriteAheadLog::snapshot_path(&path));
+        let config = HelpQueueConfig {
+            boost_requeues: true,
+            wal_path: Some(path.clone()),
+            ..HelpQueueConfig::default()
+        };
+        let clock = MockClock::default();
+        let queue = HelpQueue::with_config(config.clone(), Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        for group in 1..=4 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        clock.advance(chrono::Duration::minutes(10));
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        queue.checkpoint().expect("Error checkpointing");
+        queue.dismiss(3).await.expect("Error dismissing help");
+        queue
+            .enqueue_many(vec![(5, 887022804183175188), (6, 887022804183175189)])
+            .await
+            .expect("Error enqueueing help");
+        queue
+            .requeue_to_back(1, 887022804183175188)
+            .await
+            .expect("Error requeueing help");
+        let before_crash = queue.snapshot().unwrap();
+        drop(queue);
+
+        let recovered = HelpQueue::with_config(config, Box::new(clock))
+            .expect("Error recovering the help queue");
+        let after_crash = recovered.snapshot().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(WriteAheadLog::snapshot_path(&path)).unwrap();
+
+        assert_eq!(after_crash, before_crash);
+        assert_eq!(
+            recovered.sorted().unwrap().collect::<Vec<Group>>(),
+            vec![2, 4, 1, 5, 6]
+        );
+    }
+
+    #[tokio::test]
+    async fn test47_the_timeline_of_a_group_tells_its_story_in_order() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        let start = clock.now();
+        for group in [1, 7] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        clock.advance(chrono::Duration::minutes(5));
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        clock.advance(chrono::Duration::minutes(3));
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+
+        let timeline = queue.group_timeline(7).unwrap();
+
+        assert_eq!(
+            timeline,
+            vec![
+                (
+                    start,
+                    QueueEvent::Enqueued {
+                        group: 7,
+                        voice_channel: 887022804183175188
+                    }
+                ),
+                (
+                    start + chrono::Duration::minutes(5),
+                    QueueEvent::NewFront { group: 7 }
+                ),
+                (
+                    start + chrono::Duration::minutes(8),
+                    QueueEvent::Left {
+                        group: 7,
+                        outcome: Outcome::Served
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test48_groups_get_one_turn_per_session() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        queue
+            .enqueue_if_not_served(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+
+        let served_error = queue
+            .enqueue_if_not_served(1, 887022804183175188)
+            .await
+            .unwrap_err()
+            .downcast::<ValidationError>()
+            .unwrap();
+        let never_served = queue.enqueue_if_not_served(2, 887022804183175188).await;
+        queue.clear(None).await.expect("Error clearing the queue");
+        let after_clear = queue.enqueue_if_not_served(1, 887022804183175188).await;
+
+        assert_eq!(served_error.code, ValidationCode::AlreadyServed);
+        assert!(never_served.is_ok());
+        assert!(after_clear.is_ok());
     }
 
-    /// Returns the next group in the help queue.
-    pub async fn next(&self, helper: String) -> Result<(Group, VoiceChannel)> {
-        let next = match self.queue.read() {
-            Ok(queue) => {
-                let aux_queue = queue.clone();
-                match aux_queue.iter().min_by(|a, b| a.1 .1.cmp(&b.1 .1)) {
-                    Some(next) => *next.0,
-                    None => bail!("No group in queue"),
-                }
-            }
-            Err(error) => bail!(error.to_string()),
+    #[tokio::test]
+    async fn test49_groups_served_before_the_session_window_can_enqueue() {
+        let config = HelpQueueConfig {
+            session_window: Some(Duration::from_secs(3600)),
+            ..HelpQueueConfig::default()
         };
+        let clock = MockClock::default();
+        let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        queue.next("Ivan".to_string()).await.expect("Error helping");
 
-        print!("{} helped group {}", helper, next);
-
-        self.remove(next).await
-        // TODO: Log help.
-    }
+        clock.advance(chrono::Duration::minutes(59));
+        let within_window = queue.enqueue_if_not_served(1, 887022804183175188).await;
+        clock.advance(chrono::Duration::minutes(1));
+        let after_window = queue.enqueue_if_not_served(1, 887022804183175188).await;
 
-    /// Removes the dismisser from the help queue.
-    pub async fn dismiss(&self, dismisser: Group) -> Result<(Group, VoiceChannel)> {
-        println!("Dismissing group {} help request", dismisser);
-        self.remove(dismisser).await
-        // TODO: Log dismissal.
+        assert!(within_window.is_err());
+        assert!(after_window.is_ok());
     }
 
-    /// Clears the help queue.
-    pub async fn clear(&self) -> Result<()> {
-        match self.queue.write() {
-            Ok(mut queue) => queue.clear(),
-            Err(error) => bail!(error.to_string()),
+    #[tokio::test]
+    async fn test50_a_persisted_queue_survives_a_restart_in_order() {
+        let path = std::env::temp_dir().join(format!("algo3-{}-test50.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let queue = HelpQueue::with_persistence(&path).expect("Error creating the help queue");
+        for group in [3, 1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
         }
-        Ok(())
+        drop(queue);
+
+        let reloaded = HelpQueue::with_persistence(&path).expect("Error reloading the help queue");
+        let sorted = reloaded.sorted().unwrap().collect::<Vec<Group>>();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sorted, vec![3, 1, 2]);
     }
 
-    /// Returns the length of the help queue.
-    pub fn len(&self) -> Result<usize> {
-        match self.queue.read() {
-            Ok(queue) => Ok(queue.len()),
-            Err(error) => bail!(error.to_string()),
+    #[tokio::test]
+    async fn test51_waits_are_estimated_from_the_last_served_groups() {
+        let config = HelpQueueConfig {
+            wait_samples: Some(2),
+            default_help_duration: Some(Duration::from_secs(100)),
+            ..HelpQueueConfig::default()
+        };
+        let clock = MockClock::default();
+        let queue = HelpQueue::with_config(config, Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        for group in 1..=6 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let before_serving = queue.estimated_wait(4).unwrap();
+        for minutes in [10, 2, 2] {
+            clock.advance(chrono::Duration::minutes(minutes));
+            queue.next("Ivan".to_string()).await.expect("Error helping");
         }
+
+        // Only the last two waits, of 12 and 14 minutes, are averaged.
+        assert_eq!(before_serving, Duration::from_secs(300));
+        assert_eq!(queue.estimated_wait(4).unwrap(), Duration::from_secs(0));
+        assert_eq!(
+            queue.estimated_wait(6).unwrap(),
+            Duration::from_secs(2 * 13 * 60)
+        );
+        assert!(queue.estimated_wait(1).is_err());
     }
 
-    /// Returns whether the queue is empty or not.
-    pub fn is_empty(&self) -> Result<bool> {
-        match self.queue.read() {
-            Ok(queue) => Ok(queue.is_empty()),
-            Err(error) => bail!(error.to_string()),
+    #[tokio::test]
+    async fn test52_high_priority_groups_are_helped_first() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for (group, priority) in [
+            (1, Priority::Normal),
+            (2, Priority::High),
+            (3, Priority::Normal),
+            (4, Priority::High),
+        ] {
+            queue
+                .enqueue_with_priority(group, 887022804183175188, priority)
+                .await
+                .expect("Error enqueueing help");
         }
-    }
+        let order = queue.sorted().unwrap().collect::<Vec<Group>>();
+        let groups_ahead_of_1 = queue.status(1).unwrap().unwrap().groups_ahead;
 
-    /// Returns the help queue in order.
-    pub fn sorted(&self) -> Result<impl Iterator<Item = Group>> {
-        match self.queue.read() {
-            Ok(queue) => {
-                let aux_queue = queue.clone();
-                let sorted_scores = aux_queue
-                    .sorted_by(|_, (_, position_1), _, (_, position_2)| position_1.cmp(position_2))
-                    .map(|(group, _)| group);
-                Ok(sorted_scores)
-            }
-            Err(error) => bail!(error.to_string()),
+        let mut served = Vec::new();
+        while !queue.is_empty().unwrap() {
+            let (group, _, _) = queue.next("Ivan".to_string()).await.expect("Error helping");
+            served.push(group);
         }
+
+        assert_eq!(order, vec![2, 4, 1, 3]);
+        assert_eq!(groups_ahead_of_1, 2);
+        assert_eq!(served, vec![2, 4, 1, 3]);
     }
 
-    /// Removes a group from the help queue.
-    async fn remove(&self, group: Group) -> Result<(Group, VoiceChannel)> {
-        println!("Removing group {}", group);
-        match self.queue.write().unwrap().remove(&group) {
-            Some((voice_channel, _)) => Ok((group, voice_channel)),
-            None => bail!("Group not in queue"),
+    #[tokio::test]
+    async fn test53_the_logger_is_told_exactly_what_happened() {
+        let clock = MockClock::default();
+        let logger = MockLogger::default();
+        let queue = HelpQueue::unwrapped_with_logger(
+            HelpQueueConfig::default(),
+            Box::new(clock.clone()),
+            Box::new(logger.clone()),
+        )
+        .expect("Error creating the help queue");
+        for group in 1..=2 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
         }
-    }
-}
 
-// TODO: Solve 'Cannot start a runtime from within a runtime. This happens
-// because a function (like `block_on`) attempted to block the current thread
-// while the thread is being used to drive asynchronous tasks.'
-#[cfg(test)]
-mod help_queue_tests {
-    use super::*;
+        clock.advance(chrono::Duration::seconds(30));
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        queue.dismiss(2).await.expect("Error dismissing help");
+        queue
+            .clear(Some("Session ended".to_string()))
+            .await
+            .expect("Error clearing the queue");
 
-    #[test]
-    fn test01_help_queue_should_be_empty_when_created() {
+        assert_eq!(
+            logger.entries(),
+            vec![
+                LogEntry::Enqueue {
+                    group: 1,
+                    voice_channel: 887022804183175188,
+                    priority: Priority::Normal
+                },
+                LogEntry::Enqueue {
+                    group: 2,
+                    voice_channel: 887022804183175188,
+                    priority: Priority::Normal
+                },
+                LogEntry::Help {
+                    helper: "Ivan".to_string(),
+                    group: 1,
+                    wait: Duration::from_secs(30)
+                },
+                LogEntry::Dismiss {
+                    group: 2,
+                    wait: Duration::from_secs(30)
+                },
+                LogEntry::Clear {
+                    reason: Some("Session ended".to_string())
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test54_enqueueing_returns_the_position_in_serve_order() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        queue.dismiss(2).await.expect("Error dismissing help");
 
-        assert!(queue.is_empty().is_ok());
-        assert!(queue.is_empty().unwrap());
+        let normal_position = queue
+            .enqueue(4, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let high_position = queue
+            .enqueue_with_priority(5, 887022804183175188, Priority::High)
+            .await
+            .expect("Error enqueueing help");
+        let order = queue.sorted().unwrap().collect::<Vec<Group>>();
+
+        assert_eq!(normal_position, 1);
+        assert_eq!(high_position, 0);
+        assert_eq!(order, vec![5, 3, 4]);
     }
 
     #[tokio::test]
-    async fn test02_help_queue_should_not_be_empty_after_enqueueing() {
+    async fn test55_peeking_does_not_remove_the_next_group() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
-
+        let empty_peek = queue.peek().unwrap();
         queue
             .enqueue(1, 887022804183175188)
             .await
-            .expect("Error creating the help queue");
+            .expect("Error enqueueing help");
+        queue
+            .enqueue_with_priority(2, 887022804183175189, Priority::High)
+            .await
+            .expect("Error enqueueing help");
 
-        assert!(queue.len().is_ok());
-        assert_eq!(queue.len().unwrap(), 1);
-        assert!(queue.is_empty().is_ok());
-        assert!(!queue.is_empty().unwrap());
+        let peek = queue.peek().unwrap();
+        let (next_group, _, _) = queue.next("Ivan".to_string()).await.expect("Error helping");
+
+        assert_eq!(empty_peek, None);
+        assert_eq!(peek, Some((2, 887022804183175189)));
+        assert_eq!(next_group, 2);
     }
 
     #[tokio::test]
-    async fn test03_next_in_queue_should_be_the_last_enqueued() {
+    async fn test56_failures_tell_the_state_of_the_queue_apart() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        let empty = queue.next("Ivan".to_string()).await;
         queue
             .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
 
-        let expected_result = queue.next("Ivan".to_string()).await;
+        let already_queued = queue.enqueue(1, 887022804183175189).await;
+        let not_queued = queue.dismiss(2).await;
 
-        if let Ok((group, voice_channel)) = expected_result {
-            assert_eq!(queue.len().unwrap(), 0);
-            assert_eq!(group, 1);
-            assert_eq!(voice_channel, 887022804183175188);
+        let error = |error: anyhow::Error| error.downcast::<HelpQueueError>().unwrap();
+        assert_eq!(error(empty.unwrap_err()), HelpQueueError::Empty);
+        assert_eq!(
+            error(already_queued.unwrap_err()),
+            HelpQueueError::AlreadyQueued(1)
+        );
+        assert_eq!(error(not_queued.unwrap_err()), HelpQueueError::NotQueued(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test57_concurrent_enqueues_never_exceed_the_capacity() {
+        let config = HelpQueueConfig {
+            max_queue_size: Some(1),
+            ..HelpQueueConfig::default()
+        };
+        let queue = HelpQueue::with_config(config, Box::new(MockClock::default()))
+            .expect("Error creating the help queue");
+
+        let enqueues: Vec<_> = (1..=20)
+            .map(|group| {
+                let queue = queue.clone();
+                tokio::spawn(async move { queue.enqueue(group, 887022804183175188).await })
+            })
+            .collect();
+        let mut enqueued = Vec::new();
+        for (group, enqueue) in (1..=20).zip(enqueues) {
+            if enqueue.await.unwrap().is_ok() {
+                enqueued.push(group);
+            }
         }
+        queue
+            .dismiss(enqueued[0])
+            .await
+            .expect("Error dismissing help");
+        let after_removal = queue.enqueue(21, 887022804183175188).await;
+
+        assert_eq!(enqueued.len(), 1);
+        assert!(after_removal.is_ok());
+        assert_eq!(queue.len().unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn test04_more_than_one_group_can_request_for_help() {
+    async fn test58_groups_can_be_removed_by_voice_channel() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for (group, voice_channel) in [(1, 887022804183175188), (2, 887022804183175189)] {
+            queue
+                .enqueue(group, voice_channel)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let (group, voice_channel, _) = queue
+            .remove_by_voice_channel(887022804183175189)
+            .await
+            .expect("Error removing help");
+        let missing_error = queue
+            .remove_by_voice_channel(887022804183175189)
+            .await
+            .unwrap_err()
+            .downcast::<HelpQueueError>()
+            .unwrap();
+
+        assert_eq!((group, voice_channel), (2, 887022804183175189));
+        assert_eq!(
+            missing_error,
+            HelpQueueError::NotInVoiceChannel(887022804183175189)
+        );
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<Group>>(), vec![1]);
+        assert_eq!(queue.history().unwrap()[0].outcome, Outcome::Dismissed);
+    }
+
+    #[tokio::test]
+    async fn test59_entries_carry_positions_and_waiting_times() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
         queue
             .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(30));
         queue
             .enqueue(2, 887022804183175189)
             .await
             .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(15));
 
-        assert_eq!(queue.len().unwrap(), 2);
+        let entries = queue.entries().expect("Error reading the entries");
+
+        assert_eq!(
+            entries,
+            vec![
+                QueueEntry {
+                    group: 1,
+                    voice_channel: 887022804183175188,
+                    position: 0,
+                    waiting_secs: 45,
+                    helper: None,
+                },
+                QueueEntry {
+                    group: 2,
+                    voice_channel: 887022804183175189,
+                    position: 1,
+                    waiting_secs: 15,
+                    helper: None,
+                },
+            ]
+        );
     }
 
     #[tokio::test]
-    async fn test05_queue_behaves_fifo() {
+    async fn test60_groups_can_be_moved_to_the_back_of_the_queue() {
+        let clock = MockClock::default();
+        let queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        for (group, voice_channel) in [(1, 887022804183175188), (2, 887022804183175189)] {
+            queue
+                .enqueue(group, voice_channel)
+                .await
+                .expect("Error enqueueing help");
+        }
+        clock.advance(chrono::Duration::minutes(2));
+
+        let position = queue.requeue(1, None).await.expect("Error requeueing help");
+        let missing_error = queue
+            .requeue(3, None)
+            .await
+            .unwrap_err()
+            .downcast::<HelpQueueError>()
+            .unwrap();
+
+        assert_eq!(position, 1);
+        assert_eq!(missing_error, HelpQueueError::NotQueued(3));
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<Group>>(), vec![2, 1]);
+        let status = queue.status(1).unwrap().unwrap();
+        assert_eq!(status.voice_channel, 887022804183175188);
+        assert_eq!(status.wait_time, Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn test61_claimed_groups_stay_queued_until_completed() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+
+        let first_claim = queue.claim("Ivan").await.expect("Error claiming");
+        let second_claim = queue.claim("Ana").await.expect("Error claiming");
+        let (next_group, _, _) = queue.next("Juan".to_string()).await.unwrap();
+        let no_claim = queue.claim("Juan").await.unwrap_err();
+        let claimed_by = queue.claimed_by(1).unwrap();
+        let entries = queue.entries().unwrap();
+        let completed_by_other = queue.complete(1, "Ana").await.unwrap_err();
+        let (completed_group, _, _) = queue.complete(1, "Ivan").await.expect("Error completing");
+        let completed_again = queue.complete(1, "Ivan").await.unwrap_err();
+
+        assert_eq!(first_claim, (1, 887022804183175188));
+        assert_eq!(second_claim, (2, 887022804183175188));
+        assert_eq!(next_group, 3);
+        assert_eq!(
+            no_claim.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::Empty
+        );
+        assert_eq!(claimed_by, Some("Ivan".to_string()));
+        assert_eq!(entries[1].helper, Some("Ana".to_string()));
+        assert_eq!(
+            completed_by_other.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::ClaimedByOther(1)
+        );
+        assert_eq!(completed_group, 1);
+        assert_eq!(
+            completed_again.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::NotQueued(1)
+        );
+        assert_eq!(queue.history().unwrap()[1].helper, Some("Ivan".to_string()));
+        assert_eq!(queue.sorted().unwrap().collect::<Vec<Group>>(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test62_claims_are_forgotten_when_the_group_leaves() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
         queue
             .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
+        queue.claim("Ivan").await.expect("Error claiming");
+
+        queue.dismiss(1).await.expect("Error dismissing");
         queue
-            .enqueue(2, 887022804183175189)
+            .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
 
-        let expected_result = queue.next("Ivan".to_string()).await;
-        let other_expected_result = queue.next("Ivan".to_string()).await;
+        let unclaimed = queue.complete(1, "Ivan").await.unwrap_err();
 
-        assert_eq!(queue.len().unwrap(), 0);
-        if let Ok((group, voice_channel)) = expected_result {
-            assert_eq!(group, 1);
-            assert_eq!(voice_channel, 887022804183175188);
-        }
-        if let Ok((group, voice_channel)) = other_expected_result {
-            assert_eq!(group, 2);
-            assert_eq!(voice_channel, 887022804183175189);
-        }
+        assert_eq!(queue.claimed_by(1).unwrap(), None);
+        assert_eq!(
+            unclaimed.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::NotClaimed(1)
+        );
+        assert_eq!(queue.claim("Ana").await.unwrap().0, 1);
     }
 
     #[tokio::test]
-    async fn test06_cannot_enqueue_the_same_group_twice() {
+    async fn test63_clearing_can_return_the_cleared_groups_in_order() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
         queue
             .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
+        queue
+            .enqueue_with_priority(2, 887022804183175189, Priority::High)
+            .await
+            .expect("Error enqueueing help");
 
-        let expected_result = queue.enqueue(1, 887022804183175189).await;
+        let cleared = queue
+            .clear_and_return(Some("Session ended".to_string()))
+            .await
+            .expect("Error clearing the help queue");
 
-        assert_eq!(queue.len().unwrap(), 1);
-        assert!(expected_result.is_err());
+        assert_eq!(
+            cleared,
+            vec![(2, 887022804183175189), (1, 887022804183175188)]
+        );
+        assert!(queue.is_empty().unwrap());
     }
 
     #[tokio::test]
-    async fn test07_there_is_no_next_in_an_empty_queue() {
+    async fn test64_enqueueing_many_returns_the_positions_in_serve_order() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        queue.next("Ivan".to_string()).await.expect("Error helping");
+        queue.dismiss(2).await.expect("Error dismissing help");
 
-        let expected_result = queue.next("Ivan".to_string()).await;
+        let positions = queue
+            .enqueue_many(vec![(4, 887022804183175189), (5, 887022804183175190)])
+            .await
+            .expect("Error enqueueing help");
+        let order = queue.sorted().unwrap().collect::<Vec<Group>>();
 
-        assert!(expected_result.is_err());
+        assert_eq!(positions, vec![1, 2]);
+        assert_eq!(order, vec![3, 4, 5]);
     }
 
-    #[tokio::test]
-    async fn test08_queue_is_empty_after_clearing() {
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test65_concurrent_balanced_nexts_pick_different_helpers() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        for helper in ["Ivan", "Lucia"] {
+            queue
+                .register_helper(helper.to_string())
+                .expect("Error registering helper");
+        }
+
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = queue.clone();
+                tokio::spawn(async move { queue.next_for_balanced().await })
+            })
+            .collect();
+        let mut helpers = Vec::new();
+        for task in tasks {
+            let (helper, _, _, _) = task.await.unwrap().expect("Error helping");
+            helpers.push(helper);
+        }
+        helpers.sort();
+        let empty = queue.next_for_balanced().await;
         queue
-            .enqueue(1, 887022804183175188)
+            .enqueue(3, 887022804183175188)
             .await
             .expect("Error enqueueing help");
+        let (next_helper, _, _, _) = queue.next_for_balanced().await.expect("Error helping");
 
-        let expected_result = queue.clear().await;
-
-        assert_eq!(queue.len().unwrap(), 0);
-        assert!(expected_result.is_ok());
-        assert!(queue.is_empty().unwrap());
+        assert_eq!(helpers, vec!["Ivan".to_string(), "Lucia".to_string()]);
+        assert!(empty.is_err());
+        assert_eq!(next_helper, "Ivan");
     }
 
     #[tokio::test]
-    async fn test09_requesters_can_dismiss_their_request() {
+    async fn test66_groups_enqueued_together_keep_their_priority() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
         queue
             .enqueue(1, 887022804183175188)
             .await
             .expect("Error enqueueing help");
 
-        let expected_result = queue.dismiss(1).await;
+        let positions = queue
+            .enqueue_many_with_priority(vec![
+                (2, 887022804183175189, Priority::Normal),
+                (3, 887022804183175190, Priority::High),
+            ])
+            .await
+            .expect("Error enqueueing help");
+        let order = queue.sorted().unwrap().collect::<Vec<Group>>();
 
-        assert_eq!(queue.len().unwrap(), 0);
-        assert!(expected_result.is_ok());
-        assert_eq!(expected_result.unwrap(), (1, 887022804183175188));
+        assert_eq!(positions, vec![2, 0]);
+        assert_eq!(order, vec![3, 1, 2]);
     }
 
     #[tokio::test]
-    async fn test10_requesters_cannot_dismiss_if_they_did_not_request_for_help() {
+    async fn test67_groups_claimed_by_another_helper_cannot_be_taken() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        queue.claim("Ivan").await.expect("Error claiming");
 
-        let expected_result = queue.dismiss(2).await;
+        let provided = queue.provide_to(1, "Mallory").await.unwrap_err();
+        let drained = queue.drain("Mallory").await.unwrap_err();
+        let (completed_group, _, _) = queue.complete(1, "Ivan").await.expect("Error completing");
+        let (provided_group, _, _) = queue.provide_to(2, "Mallory").await.expect("Error helping");
 
-        assert!(expected_result.is_err());
+        assert_eq!(
+            provided.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::ClaimedByOther(1)
+        );
+        assert_eq!(
+            drained.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::ClaimedByOther(1)
+        );
+        assert_eq!((completed_group, provided_group), (1, 2));
     }
 
     #[tokio::test]
-    async fn test11_groups_that_requested_for_help_can_be_retrieved_sorted() {
+    async fn test68_requeued_groups_can_be_taken_by_another_helper() {
         let queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        queue.claim("Ivan").await.expect("Error claiming");
+
+        let stolen = queue.requeue(1, Some("Ana")).await.unwrap_err();
         queue
-            .enqueue(1, 887022804183175188)
-            .await
-            .expect("Error enqueueing help");
-        queue
-            .enqueue(2, 887022804183175189)
+            .requeue(1, Some("Ivan"))
             .await
-            .expect("Error enqueueing help");
-        queue
-            .enqueue(3, 887022804183175190)
+            .expect("Error requeueing help");
+        let (second_group, _, _) = queue.next("Ana".to_string()).await.expect("Error helping");
+        let (requeued_group, _, _) = queue.next("Ana".to_string()).await.expect("Error helping");
+
+        assert_eq!(
+            stolen.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::ClaimedByOther(1)
+        );
+        assert_eq!((second_group, requeued_group), (2, 1));
+    }
+
+    #[test]
+    fn test69_a_log_with_an_invalid_snapshot_is_not_recovered() {
+        let path = std::env::temp_dir().join(format!("algo3-{}-test69.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let entries = [0, 1]
+            .map(|position| SnapshotEntry {
+                group: 1,
+                voice_channel: 887022804183175188,
+                position,
+                enqueued_at: Utc::now(),
+                priority: Priority::Normal,
+            })
+            .to_vec();
+        wal::write_snapshot(
+            &WriteAheadLog::snapshot_path(&path),
+            &QueueSnapshot { entries },
+        )
+        .expect("Error writing the snapshot");
+        let config = HelpQueueConfig {
+            wal_path: Some(path.clone()),
+            ..HelpQueueConfig::default()
+        };
+
+        let error = HelpQueue::with_config(config, Box::new(SystemClock)).unwrap_err();
+        std::fs::remove_file(WriteAheadLog::snapshot_path(&path)).unwrap();
+
+        assert_eq!(error.to_string(), "Group 1 appears more than once");
+    }
+
+    #[test]
+    fn test70_an_invalid_persisted_queue_is_not_loaded() {
+        let path = std::env::temp_dir().join(format!("algo3-{}-test70.json", std::process::id()));
+        let entries = [1, 2]
+            .map(|group| SnapshotEntry {
+                group,
+                voice_channel: 887022804183175188,
+                position: 0,
+                enqueued_at: Utc::now(),
+                priority: Priority::Normal,
+            })
+            .to_vec();
+        wal::write_snapshot(&path, &QueueSnapshot { entries }).expect("Error writing the queue");
+
+        let error = HelpQueue::with_persistence(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.to_string(), "Position 0 appears more than once");
+    }
+
+    #[test]
+    fn test71_the_default_help_duration_cannot_be_longer_than_a_day() {
+        let config = |secs| HelpQueueConfig {
+            default_help_duration: Some(Duration::from_secs(secs)),
+            ..HelpQueueConfig::default()
+        };
+
+        let a_day = HelpQueue::with_config(config(24 * 60 * 60), Box::new(SystemClock));
+        let too_long = HelpQueue::with_config(config(u64::MAX), Box::new(SystemClock));
+
+        assert!(a_day.is_ok());
+        assert_eq!(
+            too_long.unwrap_err().to_string(),
+            "The default help duration cannot be longer than a day"
+        );
+    }
+
+    #[tokio::test]
+    async fn test72_groups_never_helped_cannot_be_requeued_to_the_back() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        let error = queue
+            .requeue_to_back(1, 887022804183175188)
             .await
-            .expect("Error enqueueing help");
+            .unwrap_err();
 
-        let expected_result = queue.sorted();
+        assert_eq!(
+            error.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::NeverHelped(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test73_helpers_taking_turns_fail_with_typed_errors() {
+        let queue = HelpQueue::new().expect("Error creating the help queue");
+
+        let no_helpers = queue.next_for_balanced().await.unwrap_err();
+        queue
+            .register_helper("Ivan".to_string())
+            .expect("Error registering helper");
+        let registered_twice = queue.register_helper("Ivan".to_string()).unwrap_err();
+        let not_registered = queue.unregister_helper("Lucia").unwrap_err();
 
-        assert!(expected_result.is_ok());
         assert_eq!(
-            expected_result.unwrap().collect::<Vec<u16>>(),
-            vec![1, 2, 3]
+            no_helpers.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::NoHelpers
+        );
+        assert_eq!(
+            registered_twice.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::HelperAlreadyRegistered("Ivan".to_string())
+        );
+        assert_eq!(
+            not_registered.downcast::<HelpQueueError>().unwrap(),
+            HelpQueueError::HelperNotRegistered("Lucia".to_string())
         );
     }
 }
@@ -0,0 +1,196 @@
+use crate::clock::{Clock, SystemClock};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Recently seen idempotency keys along with the request they were sent
+/// with, forgotten once they are older than the TTL.
+#[derive(Debug)]
+pub struct IdempotencyKeys<T> {
+    keys: RwLock<HashMap<String, (DateTime<Utc>, T)>>,
+    ttl: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl<T: Clone + Debug> IdempotencyKeys<T> {
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Self::with_clock(ttl, Box::new(SystemClock))
+    }
+
+    /// Creates a map of keys that reads the time from the given clock.
+    pub fn with_clock(ttl: Duration, clock: Box<dyn Clock>) -> Arc<Self> {
+        Arc::new(Self {
+            keys: RwLock::new(HashMap::new()),
+            ttl,
+            clock,
+        })
+    }
+
+    /// Returns the request sent with the key, if it was seen within the TTL.
+    pub fn get(&self, key: &str) -> Result<Option<T>> {
+        match self.keys.read() {
+            Ok(keys) => Ok(keys
+                .get(key)
+                .filter(|(seen_at, _)| !self.expired(*seen_at))
+                .map(|(_, request)| request.clone())),
+            Err(error) => bail!(error.to_string()),
+        }
+    }
+
+    /// Remembers the key along with its request, forgetting expired keys.
+    pub fn insert(&self, key: String, request: T) -> Result<()> {
+        match self.keys.write() {
+            Ok(mut keys) => {
+                keys.retain(|_, (seen_at, _)| !self.expired(*seen_at));
+                keys.insert(key, (self.clock.now(), request));
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Remembers the key along with its request unless it was seen within
+    /// the TTL, in which case the request it was seen with is returned.
+    /// Both happen under the same lock, so of several requests sent with
+    /// the same key at once, only one is told the key is new.
+    pub fn get_or_insert(&self, key: String, request: T) -> Result<Option<T>> {
+        match self.keys.write() {
+            Ok(mut keys) => {
+                keys.retain(|_, (seen_at, _)| !self.expired(*seen_at));
+                match keys.get(&key) {
+                    Some((_, original)) => Ok(Some(original.clone())),
+                    None => {
+                        keys.insert(key, (self.clock.now(), request));
+                        Ok(None)
+                    }
+                }
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+    }
+
+    /// Forgets the key, so its request can be sent again (e.g. after it
+    /// failed).
+    pub fn remove(&self, key: &str) -> Result<()> {
+        match self.keys.write() {
+            Ok(mut keys) => {
+                keys.remove(key);
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Returns a guard of a key inserted by `get_or_insert`, which forgets
+    /// the key when dropped unless its request was completed.
+    pub fn reservation(self: &Arc<Self>, key: String) -> Reservation<T> {
+        Reservation {
+            keys: self.clone(),
+            key: Some(key),
+        }
+    }
+
+    /// Returns whether a key seen at the given time must be forgotten.
+    fn expired(&self, seen_at: DateTime<Utc>) -> bool {
+        (self.clock.now() - seen_at)
+            .to_std()
+            .is_ok_and(|age| age >= self.ttl)
+    }
+}
+
+/// A key reserved for a request still being handled. If the request does
+/// not complete (e.g. it fails or panics), the key is forgotten once the
+/// reservation is dropped, so the request can be retried with it.
+#[derive(Debug)]
+pub struct Reservation<T: Clone + Debug> {
+    keys: Arc<IdempotencyKeys<T>>,
+    key: Option<String>,
+}
+
+impl<T: Clone + Debug> Reservation<T> {
+    /// Remembers the key along with the completed request.
+    pub fn complete(mut self, request: T) -> Result<()> {
+        match self.key.take() {
+            Some(key) => self.keys.insert(key, request),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Clone + Debug> Drop for Reservation<T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let _ = self.keys.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use super::*;
+
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test01_keys_are_remembered_along_with_their_request() {
+        let keys = IdempotencyKeys::new(Duration::from_secs(60));
+
+        keys.insert("retry".to_string(), 1).unwrap();
+
+        assert_eq!(keys.get("retry").unwrap(), Some(1));
+        assert_eq!(keys.get("other").unwrap(), None);
+    }
+
+    #[test]
+    fn test02_keys_are_forgotten_after_the_ttl() {
+        let clock = MockClock::default();
+        let keys = IdempotencyKeys::with_clock(Duration::from_secs(60), Box::new(clock.clone()));
+        keys.insert("retry".to_string(), 1).unwrap();
+
+        clock.advance(chrono::Duration::seconds(59));
+        let before_ttl = keys.get("retry").unwrap();
+        clock.advance(chrono::Duration::seconds(1));
+        let after_ttl = keys.get("retry").unwrap();
+
+        assert_eq!(before_ttl, Some(1));
+        assert_eq!(after_ttl, None);
+    }
+
+    #[test]
+    fn test03_only_the_first_request_with_a_key_gets_to_insert_it() {
+        let keys = IdempotencyKeys::new(Duration::from_secs(60));
+
+        let first = keys.get_or_insert("retry".to_string(), 1).unwrap();
+        let second = keys.get_or_insert("retry".to_string(), 2).unwrap();
+        keys.remove("retry").unwrap();
+        let after_removal = keys.get_or_insert("retry".to_string(), 3).unwrap();
+
+        assert_eq!(first, None);
+        assert_eq!(second, Some(1));
+        assert_eq!(after_removal, None);
+        assert_eq!(keys.get("retry").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test04_reservations_are_forgotten_unless_completed() {
+        let keys = IdempotencyKeys::new(Duration::from_secs(60));
+        keys.get_or_insert("completed".to_string(), 0).unwrap();
+        keys.get_or_insert("panicked".to_string(), 0).unwrap();
+
+        keys.reservation("completed".to_string())
+            .complete(1)
+            .unwrap();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _reservation = keys.reservation("panicked".to_string());
+            panic!("Deliberate panic");
+        }));
+
+        assert!(panicked.is_err());
+        assert_eq!(keys.get("completed").unwrap(), Some(1));
+        assert_eq!(keys.get("panicked").unwrap(), None);
+    }
+}
@@ -1,3 +1,19 @@
+pub mod audit_log;
+
+pub mod clock;
+
+pub mod events;
+
 pub mod help_queue;
 
+pub mod idempotency;
+
+pub mod logger;
+
+pub mod metrics;
+
+pub mod rate_limit;
+
+pub mod wal;
+
 pub mod web_server;
@@ -0,0 +1,128 @@
+use crate::help_queue::{Group, Priority, VoiceChannel};
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+/// Where the help queue reports what happened to it, so the reports can be
+/// checked in tests.
+pub trait Logger: Debug + Send + Sync {
+    /// Reports a group joining the queue.
+    fn log_enqueue(&self, group: Group, voice_channel: VoiceChannel, priority: Priority);
+
+    /// Reports a group being helped after the given wait.
+    fn log_help(&self, helper: &str, group: Group, wait: Duration);
+
+    /// Reports a group leaving the queue on its own after the given wait.
+    fn log_dismiss(&self, group: Group, wait: Duration);
+
+    /// Reports the queue being cleared.
+    fn log_clear(&self, reason: Option<&str>);
+}
+
+/// A logger that emits a tracing event for each report.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingLogger;
+
+impl Logger for TracingLogger {
+    fn log_enqueue(&self, group: Group, voice_channel: VoiceChannel, priority: Priority) {
+        info!(group, voice_channel, ?priority, "Group enqueued");
+    }
+
+    fn log_help(&self, helper: &str, group: Group, wait: Duration) {
+        info!(helper, group, wait_seconds = wait.as_secs(), "Group helped");
+    }
+
+    fn log_dismiss(&self, group: Group, wait: Duration) {
+        info!(group, wait_seconds = wait.as_secs(), "Group dismissed");
+    }
+
+    fn log_clear(&self, reason: Option<&str>) {
+        info!(reason, "Help queue cleared");
+    }
+}
+
+/// A report, as captured by the `MockLogger`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEntry {
+    Enqueue {
+        group: Group,
+        voice_channel: VoiceChannel,
+        priority: Priority,
+    },
+    Help {
+        helper: String,
+        group: Group,
+        wait: Duration,
+    },
+    Dismiss {
+        group: Group,
+        wait: Duration,
+    },
+    Clear {
+        reason: Option<String>,
+    },
+}
+
+/// A logger that keeps the reports instead of emitting them. Clones share
+/// the same reports, so a test can keep one and hand another to the code
+/// under test.
+#[derive(Debug, Default, Clone)]
+pub struct MockLogger {
+    entries: Arc<Mutex<Vec<LogEntry>>>,
+}
+
+impl MockLogger {
+    /// Returns the reports so far, oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    fn push(&self, entry: LogEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+}
+
+impl Logger for MockLogger {
+    fn log_enqueue(&self, group: Group, voice_channel: VoiceChannel, priority: Priority) {
+        self.push(LogEntry::Enqueue {
+            group,
+            voice_channel,
+            priority,
+        });
+    }
+
+    fn log_help(&self, helper: &str, group: Group, wait: Duration) {
+        self.push(LogEntry::Help {
+            helper: helper.to_string(),
+            group,
+            wait,
+        });
+    }
+
+    fn log_dismiss(&self, group: Group, wait: Duration) {
+        self.push(LogEntry::Dismiss { group, wait });
+    }
+
+    fn log_clear(&self, reason: Option<&str>) {
+        self.push(LogEntry::Clear {
+            reason: reason.map(str::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod logger_tests {
+    use super::*;
+
+    #[test]
+    fn test01_mock_logger_clones_share_the_same_entries() {
+        let logger = MockLogger::default();
+        let other_logger = logger.clone();
+
+        other_logger.log_clear(None);
+
+        assert_eq!(logger.entries(), vec![LogEntry::Clear { reason: None }]);
+    }
+}
@@ -1,9 +1,11 @@
-use algo3_backend::web_server::{ServerArguments, WebServer};
-
-use clap::Parser;
+use algo3_backend::web_server::{self, ServerArguments, WebServer};
 
 fn main() {
-    match WebServer::start(ServerArguments::parse()) {
+    web_server::log_panics();
+    match ServerArguments::parse_with_config()
+        .and_then(WebServer::start)
+        .and_then(|server| server.wait())
+    {
         Ok(_) => {}
         Err(error) => eprintln!("Error al correr el servidor: {}", error),
     }
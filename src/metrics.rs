@@ -0,0 +1,158 @@
+use crate::help_queue::HelpQueue;
+
+use anyhow::{bail, Result};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+/// Prefix of every metric name.
+const PREFIX: &str = "algo3";
+
+/// Counters of what happened in the help queue.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    enqueues: AtomicU64,
+    serves: AtomicU64,
+    dismissals: AtomicU64,
+    /// Waits of the groups served since the last flush.
+    waits: Mutex<Vec<Duration>>,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Counts groups joining the queue.
+    pub fn record_enqueues(&self, count: u64) {
+        self.enqueues.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Counts a group being helped after the given wait.
+    pub fn record_serve(&self, wait: Duration) -> Result<()> {
+        self.serves.fetch_add(1, Ordering::Relaxed);
+        match self.waits.lock() {
+            Ok(mut waits) => waits.push(wait),
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Counts a group leaving the queue on its own.
+    pub fn record_dismissal(&self) {
+        self.dismissals.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the metrics in StatsD format, resetting the counters.
+    pub fn flush(&self, queue_size: usize) -> Result<String> {
+        let mut payload = format!("{PREFIX}.queue_size:{queue_size}|g\n");
+        for (name, counter) in [
+            ("enqueues", &self.enqueues),
+            ("serves", &self.serves),
+            ("dismissals", &self.dismissals),
+        ] {
+            let _ = writeln!(
+                payload,
+                "{PREFIX}.{name}:{}|c",
+                counter.swap(0, Ordering::Relaxed)
+            );
+        }
+        match self.waits.lock() {
+            Ok(mut waits) => {
+                for wait in waits.drain(..) {
+                    let _ = writeln!(payload, "{PREFIX}.wait_time:{}|ms", wait.as_millis());
+                }
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(payload)
+    }
+}
+
+/// Pushes the metrics of the help queue to a StatsD server every `interval`.
+/// Failures are logged and retried on the next tick.
+pub async fn export_statsd(help_queue: Arc<HelpQueue>, address: SocketAddr, interval: Duration) {
+    let bind_address: SocketAddr = match address {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = match UdpSocket::bind(bind_address).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            warn!("Error binding the StatsD socket: {}", error);
+            return;
+        }
+    };
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let payload = help_queue
+            .len()
+            .and_then(|queue_size| help_queue.metrics().flush(queue_size));
+        match payload {
+            Ok(payload) => {
+                if let Err(error) = socket.send_to(payload.as_bytes(), address).await {
+                    warn!("Error sending metrics to {}: {}", address, error);
+                }
+            }
+            Err(error) => warn!("Error collecting metrics: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn test01_flushing_reports_and_resets_the_counters() {
+        let metrics = Metrics::new();
+        metrics.record_enqueues(2);
+        metrics.record_serve(Duration::from_secs(3)).unwrap();
+        metrics.record_dismissal();
+
+        let first_flush = metrics.flush(0).unwrap();
+        let second_flush = metrics.flush(0).unwrap();
+
+        assert_eq!(
+            first_flush,
+            "algo3.queue_size:0|g\nalgo3.enqueues:2|c\nalgo3.serves:1|c\nalgo3.dismissals:1|c\nalgo3.wait_time:3000|ms\n"
+        );
+        assert_eq!(
+            second_flush,
+            "algo3.queue_size:0|g\nalgo3.enqueues:0|c\nalgo3.serves:0|c\nalgo3.dismissals:0|c\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test02_metrics_are_pushed_to_the_statsd_server() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let statsd = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = statsd.local_addr().unwrap();
+
+        let exporter = tokio::spawn(export_statsd(
+            help_queue,
+            address,
+            Duration::from_millis(10),
+        ));
+        let mut datagram = [0; 1024];
+        let (length, _) =
+            tokio::time::timeout(Duration::from_secs(5), statsd.recv_from(&mut datagram))
+                .await
+                .expect("No metrics were received")
+                .unwrap();
+        exporter.abort();
+
+        let payload = std::str::from_utf8(&datagram[..length]).unwrap();
+        assert!(payload.contains("algo3.queue_size:1|g"));
+        assert!(payload.contains("algo3.enqueues:1|c"));
+    }
+}
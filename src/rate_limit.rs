@@ -0,0 +1,197 @@
+use crate::clock::{Clock, SystemClock};
+use crate::help_queue::Group;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// How often buckets that refilled are forgotten.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The error returned when a group acts faster than it is allowed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitedError {
+    pub group: Group,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimitedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Group {} is acting too often, retry in {} seconds",
+            self.group,
+            self.retry_after.as_secs_f64().ceil()
+        )
+    }
+}
+
+impl std::error::Error for RateLimitedError {}
+
+/// The tokens a group has left and when they were last counted.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+}
+
+/// The buckets of every group, and when idle ones were last forgotten.
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<Group, Bucket>,
+    swept_at: DateTime<Utc>,
+}
+
+/// A token bucket per group: a group can act `burst` times in a row, and
+/// earns back one action every `interval`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: RwLock<Buckets>,
+    burst: u32,
+    interval: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter. A `burst` of 0 lets every action through.
+    pub fn new(burst: u32, interval: Duration) -> Arc<Self> {
+        Self::with_clock(burst, interval, Box::new(SystemClock))
+    }
+
+    /// Creates a limiter that reads the time from the given clock.
+    pub fn with_clock(burst: u32, interval: Duration, clock: Box<dyn Clock>) -> Arc<Self> {
+        Arc::new(Self {
+            buckets: RwLock::new(Buckets {
+                buckets: HashMap::new(),
+                swept_at: clock.now(),
+            }),
+            burst,
+            interval,
+            clock,
+        })
+    }
+
+    /// Takes a token from the group's bucket, failing with a
+    /// `RateLimitedError` if it has none left.
+    pub fn check(&self, group: Group) -> Result<()> {
+        if self.burst == 0 || self.interval.is_zero() {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        let mut buckets = match self.buckets.write() {
+            Ok(buckets) => buckets,
+            Err(error) => bail!(error.to_string()),
+        };
+        if self.elapsed(buckets.swept_at, now) >= SWEEP_INTERVAL {
+            buckets
+                .buckets
+                .retain(|_, bucket| self.refilled(bucket, now) < self.burst as f64);
+            buckets.swept_at = now;
+        }
+
+        let bucket = buckets.buckets.entry(group).or_insert(Bucket {
+            tokens: self.burst as f64,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+        if bucket.tokens < 1.0 {
+            bail!(RateLimitedError {
+                group,
+                retry_after: self.interval.mul_f64(1.0 - bucket.tokens),
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Returns the tokens the bucket has at the given time.
+    fn refilled(&self, bucket: &Bucket, now: DateTime<Utc>) -> f64 {
+        let earned =
+            self.elapsed(bucket.updated_at, now).as_secs_f64() / self.interval.as_secs_f64();
+        (bucket.tokens + earned).min(self.burst as f64)
+    }
+
+    fn elapsed(&self, since: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+        (now - since).to_std().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    use crate::clock::MockClock;
+
+    fn rate_limited(limiter: &RateLimiter, group: Group) -> Option<RateLimitedError> {
+        limiter
+            .check(group)
+            .err()
+            .map(|error| error.downcast::<RateLimitedError>().unwrap())
+    }
+
+    #[test]
+    fn test01_groups_are_limited_once_they_use_up_their_burst() {
+        let limiter =
+            RateLimiter::with_clock(2, Duration::from_secs(4), Box::new(MockClock::default()));
+
+        limiter.check(1).unwrap();
+        limiter.check(1).unwrap();
+        let error = rate_limited(&limiter, 1);
+        let other_group = limiter.check(2);
+
+        assert_eq!(
+            error,
+            Some(RateLimitedError {
+                group: 1,
+                retry_after: Duration::from_secs(4),
+            })
+        );
+        assert!(other_group.is_ok());
+    }
+
+    #[test]
+    fn test02_groups_earn_back_a_token_every_interval() {
+        let clock = MockClock::default();
+        let limiter = RateLimiter::with_clock(1, Duration::from_secs(4), Box::new(clock.clone()));
+        limiter.check(1).unwrap();
+
+        clock.advance(chrono::Duration::seconds(3));
+        let before_interval = rate_limited(&limiter, 1).map(|error| error.retry_after);
+        clock.advance(chrono::Duration::seconds(1));
+        let after_interval = limiter.check(1);
+
+        assert_eq!(before_interval, Some(Duration::from_secs(1)));
+        assert!(after_interval.is_ok());
+    }
+
+    #[test]
+    fn test03_idle_buckets_are_forgotten() {
+        let clock = MockClock::default();
+        let limiter = RateLimiter::with_clock(1, Duration::from_secs(4), Box::new(clock.clone()));
+        limiter.check(1).unwrap();
+        limiter.check(2).unwrap();
+
+        clock.advance(chrono::Duration::seconds(2));
+        limiter.check(3).unwrap();
+        let before_sweep = limiter.buckets.read().unwrap().buckets.len();
+        clock.advance(chrono::Duration::seconds(60));
+        limiter.check(3).unwrap();
+        let after_sweep = limiter.buckets.read().unwrap().buckets.len();
+
+        assert_eq!(before_sweep, 3);
+        assert_eq!(after_sweep, 1);
+    }
+
+    #[test]
+    fn test04_a_burst_of_zero_disables_the_limit() {
+        let limiter = RateLimiter::new(0, Duration::from_secs(4));
+
+        for _ in 0..100 {
+            limiter.check(1).unwrap();
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::help_queue::{Group, HelpQueue, QueueSnapshot, SnapshotEntry};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::warn;
+
+/// A change to the help queue, as it is written to the log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalRecord {
+    /// Groups joined the queue. The groups at or behind the position of
+    /// each one are moved one position back.
+    Enqueued {
+        entries: Vec<SnapshotEntry>,
+    },
+    /// A group left the queue, either served or dismissed.
+    Removed {
+        group: Group,
+    },
+    Cleared,
+    Restored {
+        snapshot: QueueSnapshot,
+    },
+}
+
+/// An append-only log of the changes to the help queue since its last
+/// snapshot, so that they survive a crash.
+///
+/// The snapshot is kept next to the log, with the `snapshot` extension.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl WriteAheadLog {
+    /// Opens the log at the given path, creating it if missing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Returns where the snapshot of the log at the given path is kept.
+    pub fn snapshot_path(path: &Path) -> PathBuf {
+        path.with_extension("snapshot")
+    }
+
+    /// Reads the last snapshot and the changes logged after it, if any.
+    ///
+    /// A torn last record is cut off the log, so the records appended after
+    /// recovering start on a line of their own.
+    pub fn recover(path: &Path) -> Result<(QueueSnapshot, Vec<WalRecord>)> {
+        let snapshot = read_snapshot(&Self::snapshot_path(path))?;
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok((snapshot, Vec::new())),
+            Err(error) => bail!(error),
+        };
+        let lines: Vec<&[u8]> = bytes.split_inclusive(|byte| *byte == b'\n').collect();
+        let mut records = Vec::new();
+        let mut good_len = 0;
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_slice(line) {
+                Ok(record) => {
+                    records.push(record);
+                    good_len += line.len();
+                }
+                // A crash in the middle of a write leaves the last line torn.
+                Err(error) if index == lines.len() - 1 => {
+                    warn!("Ignoring the torn last record of the log: {}", error)
+                }
+                Err(error) => bail!("Invalid record {} in the log: {}", index + 1, error),
+            }
+        }
+        if good_len < bytes.len() {
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(good_len as u64)?;
+            file.sync_all()?;
+        } else if bytes.last().is_some_and(|byte| *byte != b'\n') {
+            // The last record is whole but its line was never ended.
+            let mut file = OpenOptions::new().append(true).open(path)?;
+            file.write_all(b"\n")?;
+            file.sync_all()?;
+        }
+        Ok((snapshot, records))
+    }
+
+    /// Appends a change to the log, returning once it is on disk.
+    pub fn append(&self, record: &WalRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        match self.file.lock() {
+            Ok(mut file) => {
+                file.write_all(&line)?;
+                file.sync_data()?;
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Writes a new snapshot and empties the log, as the snapshot already
+    /// has its changes.
+    pub fn compact(&self, snapshot: &QueueSnapshot) -> Result<()> {
+        write_snapshot(&Self::snapshot_path(&self.path), snapshot)?;
+        match self.file.lock() {
+            Ok(file) => {
+                file.set_len(0)?;
+                file.sync_all()?;
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+        Ok(())
+    }
+}
+
+/// Reads the snapshot at the given path, or an empty one if it is missing.
+pub fn read_snapshot(path: &Path) -> Result<QueueSnapshot> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(QueueSnapshot::default()),
+        Err(error) => bail!(error),
+    }
+}
+
+/// Writes the snapshot to the given path, replacing the previous one.
+pub fn write_snapshot(path: &Path, snapshot: &QueueSnapshot) -> Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let mut temporary = File::create(&temporary_path)?;
+    temporary.write_all(&serde_json::to_vec(snapshot)?)?;
+    temporary.sync_all()?;
+    // Renaming is atomic, so a crash leaves either snapshot whole.
+    fs::rename(&temporary_path, path)?;
+    Ok(())
+}
+
+/// Writes snapshots of the help queue on a thread of its own, so changes do
+/// not wait on the disk. Snapshots still pending when a newer one arrives
+/// are skipped, and dropping the writer waits for the last one to be
+/// written.
+#[derive(Debug)]
+pub struct SnapshotWriter {
+    sender: Option<Sender<QueueSnapshot>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SnapshotWriter {
+    /// Starts writing the snapshots sent to the writer to the given path.
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+        let (sender, receiver) = mpsc::channel::<QueueSnapshot>();
+        let thread = thread::Builder::new()
+            .name("snapshot-writer".to_string())
+            .spawn(move || {
+                while let Ok(mut snapshot) = receiver.recv() {
+                    while let Ok(newer) = receiver.try_recv() {
+                        snapshot = newer;
+                    }
+                    // The change already applies, so a failure to save it is
+                    // only warned about.
+                    if let Err(error) = write_snapshot(&path, &snapshot) {
+                        warn!("Error persisting the help queue: {}", error);
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues the snapshot to be written.
+    pub fn write(&self, snapshot: QueueSnapshot) {
+        let sent = match &self.sender {
+            Some(sender) => sender.send(snapshot).is_ok(),
+            None => false,
+        };
+        if !sent {
+            warn!("Error persisting the help queue: the writer stopped");
+        }
+    }
+}
+
+impl Drop for SnapshotWriter {
+    fn drop(&mut self) {
+        // Closing the channel lets the thread finish the pending snapshot.
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Snapshots the help queue every `interval`, compacting its log.
+/// Failures are logged and retried on the next tick.
+pub async fn checkpoint_periodically(help_queue: Arc<HelpQueue>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        if let Err(error) = help_queue.checkpoint() {
+            warn!("Error checkpointing the help queue: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod wal_tests {
+    use super::*;
+
+    use crate::help_queue::Priority;
+
+    use chrono::Utc;
+
+    #[test]
+    fn test01_appended_records_are_recovered_in_order() {
+        let path =
+            std::env::temp_dir().join(format!("algo3-wal-{}-test01.wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let records = vec![
+            WalRecord::Enqueued {
+                entries: vec![SnapshotEntry {
+                    group: 1,
+                    voice_channel: 887022804183175188,
+                    position: 0,
+                    enqueued_at: Utc::now(),
+                    priority: Priority::Normal,
+                }],
+            },
+            WalRecord::Removed { group: 1 },
+            WalRecord::Cleared,
+        ];
+
+        let wal = WriteAheadLog::open(&path).unwrap();
+        for record in &records {
+            wal.append(record).unwrap();
+        }
+        let (snapshot, recovered) = WriteAheadLog::recover(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshot, QueueSnapshot::default());
+        assert_eq!(recovered, records);
+    }
+
+    #[test]
+    fn test02_records_appended_after_a_torn_tail_are_recovered() {
+        let path =
+            std::env::temp_dir().join(format!("algo3-wal-{}-test02.wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+        fs::write(&path, "{\"op\":\"cleared\"}\n{\"op\":\"enq").unwrap();
+
+        let (_, first_recovery) = WriteAheadLog::recover(&path).unwrap();
+        let wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&WalRecord::Removed { group: 1 }).unwrap();
+        wal.append(&WalRecord::Removed { group: 2 }).unwrap();
+        drop(wal);
+        let (_, second_recovery) = WriteAheadLog::recover(&path).unwrap();
+        let (_, third_recovery) = WriteAheadLog::recover(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first_recovery, vec![WalRecord::Cleared]);
+        let expected = vec![
+            WalRecord::Cleared,
+            WalRecord::Removed { group: 1 },
+            WalRecord::Removed { group: 2 },
+        ];
+        assert_eq!(second_recovery, expected);
+        assert_eq!(third_recovery, expected);
+    }
+
+    #[test]
+    fn test03_the_snapshot_writer_writes_the_last_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("algo3-wal-{}-test03.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let snapshots: Vec<QueueSnapshot> = (0..3)
+            .map(|count| QueueSnapshot {
+                entries: (0..count)
+                    .map(|position| SnapshotEntry {
+                        group: position as Group + 1,
+                        voice_channel: 887022804183175188,
+                        position,
+                        enqueued_at: Utc::now(),
+                        priority: Priority::Normal,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let writer = SnapshotWriter::spawn(&path).unwrap();
+        for snapshot in &snapshots {
+            writer.write(snapshot.clone());
+        }
+        drop(writer);
+        let written = read_snapshot(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, snapshots[2]);
+    }
+}
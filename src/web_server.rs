@@ -1,22 +1,139 @@
-use crate::help_queue::HelpQueue;
+use crate::audit_log::{AuditAction, AuditLog};
+use crate::clock::SystemClock;
+use crate::events;
+use crate::help_queue::{
+    CooldownError, GroupNotAllowedError, HelpQueue, HelpQueueConfig, HelpQueueError, Priority,
+    QueueSnapshot, ValidationCode, ValidationError, DEFAULT_HELP_DURATION, DEFAULT_HISTORY_SIZE,
+    DEFAULT_WAIT_SAMPLES,
+};
+use crate::idempotency::IdempotencyKeys;
+use crate::metrics;
+use crate::rate_limit::{RateLimitedError, RateLimiter};
+use crate::wal;
 
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser, ValueSource};
+use futures_util::future::{self, BoxFuture};
+use futures_util::{stream, FutureExt, SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::ffi::OsString;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{oneshot, watch};
 use tokio::task::JoinHandle;
-use warp::{hyper::StatusCode, reject, reply, Filter, Rejection, Reply};
+use tokio_rustls::{rustls, TlsAcceptor};
+use tracing::{error, info, warn, Level};
+use uuid::Uuid;
+use warp::{
+    filters::{body::BodyDeserializeError, path::FullPath, BoxedFilter},
+    http::{header, Method},
+    hyper::{
+        self,
+        server::{
+            accept::{self, Accept},
+            conn::AddrIncoming,
+        },
+        service::{make_service_fn, service_fn, Service},
+        Body, StatusCode,
+    },
+    reject, reply,
+    ws::{Message, WebSocket, Ws},
+    Filter, Rejection, Reply,
+};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 struct Requester {
     group: u16,
     voice_channel: u64,
+    #[serde(default)]
+    priority: Priority,
+}
+
+/// A requester asking to be enqueued, optionally with a key that makes
+/// retries of the request safe (as the `Idempotency-Key` header does).
+#[derive(Deserialize, Debug)]
+struct EnqueueRequest {
+    #[serde(flatten)]
+    requester: Requester,
+    idempotency_key: Option<String>,
+}
+
+/// A helper asking for the next group, skipping the given ones (e.g. groups
+/// whose voice channel is empty).
+#[derive(Deserialize, Debug)]
+struct NextRequest {
+    #[serde(default)]
+    helper: Option<String>,
+    #[serde(default)]
+    skip: Vec<u16>,
+}
+
+/// A helper acting on the help queue. The helper named in the body is only
+/// used when the request is not authenticated with a helper token.
+#[derive(Deserialize, Debug)]
+struct HelperRequest {
+    #[serde(default)]
+    helper: Option<String>,
+}
+
+/// Options of a clear of the help queue.
+#[derive(Deserialize, Debug, Default)]
+struct ClearOptions {
+    /// Why the queue is cleared (e.g. "Session ended").
+    reason: Option<String>,
+    /// Whether to respond with the groups that were cleared, so they can be
+    /// told.
+    #[serde(default)]
+    notify: bool,
+}
+
+/// Options of a read of the help queue.
+#[derive(Deserialize, Debug, Default)]
+struct HelpQueueOptions {
+    /// Whether to list each group with its voice channel and wait, instead
+    /// of only the group numbers.
+    #[serde(default)]
+    detailed: bool,
+}
+
+/// Statistics about the help queue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct QueueStats {
+    length: usize,
+}
+
+/// A backup of the help queue. The stats are informative and are not needed
+/// to restore it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct QueueExport {
+    snapshot: QueueSnapshot,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<QueueStats>,
 }
 
 /// An enum of error handlers for the server.
 #[derive(Debug)]
 enum ServerError {
     Request(String),
+    BadRequest(String),
+    Conflict(String),
+    Unauthorized,
+    Validation(ValidationError),
+    Cooldown(CooldownError),
+    RateLimited(RateLimitedError),
+    Queue(HelpQueueError),
 }
 
 impl reject::Reject for ServerError {}
@@ -30,7 +147,25 @@ pub trait OrReject<T> {
 impl<T> OrReject<T> for anyhow::Result<T> {
     /// Returns the result if it is successful, otherwise returns a rejection.
     fn or_reject(self) -> Result<T, Rejection> {
-        self.map_err(|e| reject::custom(ServerError::Request(e.to_string())))
+        self.map_err(|e| {
+            if let Some(cooldown) = e.downcast_ref::<CooldownError>() {
+                reject::custom(ServerError::Cooldown(*cooldown))
+            } else if let Some(rate_limited) = e.downcast_ref::<RateLimitedError>() {
+                reject::custom(ServerError::RateLimited(*rate_limited))
+            } else if e.downcast_ref::<GroupNotAllowedError>().is_some() {
+                reject::custom(ServerError::Validation(ValidationError::new(
+                    "group",
+                    ValidationCode::NotAllowed,
+                    e.to_string(),
+                )))
+            } else if let Some(validation) = e.downcast_ref::<ValidationError>() {
+                reject::custom(ServerError::Validation(validation.clone()))
+            } else if let Some(queue_error) = e.downcast_ref::<HelpQueueError>() {
+                reject::custom(ServerError::Queue(queue_error.clone()))
+            } else {
+                reject::custom(ServerError::Request(e.to_string()))
+            }
+        })
     }
 }
 
@@ -42,6 +177,26 @@ pub enum HelpQueueRequest {
     Dismiss(u16),
 }
 
+/// The set of routes a listener serves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Listener {
+    /// Every route, used when there is no separate admin port.
+    All,
+    /// Every route but the admin ones.
+    Public,
+    /// Only the admin routes.
+    Admin,
+}
+
+/// How the server writes its logs.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable logs, for local development.
+    Pretty,
+    /// One JSON object per line, for log ingestion in production.
+    Json,
+}
+
 /// Arguments that serve as config for the server.
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -50,6 +205,131 @@ pub struct ServerArguments {
     domain: String,
     #[clap(short, long, value_parser, default_value_t = 80)]
     port: u16,
+    /// Port that serves the admin routes exclusively (served on `port` if unset).
+    #[clap(long, value_parser)]
+    admin_port: Option<u16>,
+    /// Address to listen on. Use `::` for IPv6, which also accepts IPv4
+    /// connections on dual-stack systems (the default on Linux).
+    #[clap(short, long, value_parser, default_value = "0.0.0.0")]
+    address: IpAddr,
+    /// Origins allowed to perform cross-origin requests (any origin if empty).
+    #[clap(long = "allowed-origin", value_parser)]
+    allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests (the ones the routes use if
+    /// empty).
+    #[clap(long = "allowed-method", value_parser)]
+    allowed_methods: Vec<String>,
+    /// Headers allowed in cross-origin requests (the ones the routes read if
+    /// empty).
+    #[clap(long = "allowed-header", value_parser)]
+    allowed_headers: Vec<String>,
+    /// Whether to allow credentials (cookies, auth headers) in CORS requests.
+    #[clap(long, value_parser)]
+    cors_allow_credentials: bool,
+    /// Admin credentials as `name:token` pairs (admin routes are open if empty).
+    #[clap(long = "admin-token", value_parser)]
+    admin_tokens: Vec<String>,
+    /// Helper credentials as `name:token` pairs. If any, helpers serving
+    /// groups are named after their token instead of the name they send.
+    #[clap(long = "helper-token", value_parser)]
+    helper_tokens: Vec<String>,
+    /// Seconds a group must wait to enqueue again after leaving the queue.
+    #[clap(long, value_parser, default_value_t = 0)]
+    cooldown_secs: u64,
+    /// How many groups can wait in the same voice channel (unlimited if unset).
+    #[clap(long, value_parser)]
+    max_groups_per_voice_channel: Option<usize>,
+    /// How many groups can wait in the queue (unlimited if unset).
+    #[clap(long, value_parser)]
+    max_queue_size: Option<usize>,
+    /// Groups allowed to enqueue (any group if empty).
+    #[clap(long = "allowed-group", value_parser)]
+    allowed_groups: Vec<u16>,
+    /// Groups that can never enqueue (e.g. the staff's test group).
+    #[clap(long = "denied-group", value_parser)]
+    denied_groups: Vec<u16>,
+    /// Path under which every route is mounted (e.g. `/algo3`).
+    #[clap(long, value_parser, default_value = "")]
+    base_path: String,
+    /// Seconds to let in-flight requests finish when shutting down.
+    #[clap(long, value_parser, default_value_t = 30)]
+    drain_timeout_secs: u64,
+    /// StatsD server (`host:port`) to push metrics to (disabled if unset).
+    #[clap(long, value_parser)]
+    statsd_addr: Option<SocketAddr>,
+    /// Seconds between pushes of metrics to StatsD.
+    #[clap(long, value_parser, default_value_t = 10)]
+    statsd_interval_secs: u64,
+    /// Format of the logs.
+    #[clap(long, value_enum, default_value = "pretty")]
+    log_format: LogFormat,
+    /// Most verbose level of the logs (`error`, `warn`, `info`, `debug` or
+    /// `trace`).
+    #[clap(long, value_parser, default_value = "info")]
+    log_level: Level,
+    /// How many queue events are kept for subscribers that fall behind.
+    #[clap(long, value_parser)]
+    event_capacity: Option<usize>,
+    /// How many groups that left the queue are kept in the history.
+    #[clap(long, value_parser, default_value_t = DEFAULT_HISTORY_SIZE)]
+    history_size: usize,
+    /// How many of the last waits are averaged to estimate waits.
+    #[clap(long, value_parser, default_value_t = DEFAULT_WAIT_SAMPLES)]
+    wait_samples: usize,
+    /// Seconds helping a group is assumed to take until a group is served,
+    /// at most a day.
+    #[clap(long, value_parser, default_value_t = DEFAULT_HELP_DURATION.as_secs())]
+    default_help_secs: u64,
+    /// Seconds back a group counts as already served when it enqueues only
+    /// if not served (since the last clear if unset).
+    #[clap(long, value_parser)]
+    session_window_secs: Option<u64>,
+    /// Whether groups that come back after being helped go ahead of the
+    /// groups that have waited less than they did in total, instead of
+    /// behind every queued group.
+    #[clap(long, value_parser)]
+    boost_requeues: bool,
+    /// Seconds that must pass between two announcements of the group in
+    /// front of the queue (every new front is announced if 0).
+    #[clap(long, value_parser, default_value_t = 0)]
+    front_debounce_secs: u64,
+    /// How many enqueues and dismissals a group can make in a row (not
+    /// limited if 0).
+    #[clap(long, value_parser, default_value_t = 5)]
+    rate_limit_burst: u32,
+    /// Seconds it takes a group to earn back one enqueue or dismissal.
+    #[clap(long, value_parser, default_value_t = 2)]
+    rate_limit_interval_secs: u64,
+    /// Seconds an `Idempotency-Key` is remembered after an enqueue.
+    #[clap(long, value_parser, default_value_t = 300)]
+    idempotency_ttl_secs: u64,
+    /// Whether to wrap JSON responses in a `{"data": ..., "error": ...}`
+    /// envelope instead of returning them bare.
+    #[clap(long, value_parser)]
+    response_envelope: bool,
+    /// Where to log the changes to the queue so they survive a crash (not
+    /// logged if unset). The snapshot is kept next to it.
+    #[clap(long, value_parser)]
+    wal_path: Option<PathBuf>,
+    /// Where to save the whole queue after every change, to load it back on
+    /// restart (kept in memory only if unset).
+    #[clap(long, value_parser)]
+    persistence_path: Option<PathBuf>,
+    /// Seconds between snapshots of the queue, which empty its log.
+    #[clap(long, value_parser, default_value_t = 60)]
+    wal_checkpoint_secs: u64,
+    /// PEM certificate chain to serve HTTPS with, along with `tls_key`
+    /// (plain HTTP if both are unset).
+    #[clap(long, value_parser)]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of `tls_cert`.
+    #[clap(long, value_parser)]
+    tls_key: Option<PathBuf>,
+    /// TOML (or JSON, if named `*.json`) file to read the arguments missing
+    /// from the command line from, keyed by their snake_case names (e.g.
+    /// `max_queue_size = 30`).
+    #[clap(long, value_parser)]
+    config: Option<PathBuf>,
 }
 
 impl Clone for ServerArguments {
@@ -57,6 +337,42 @@ impl Clone for ServerArguments {
         Self {
             domain: self.domain.clone(),
             port: self.port,
+            admin_port: self.admin_port,
+            address: self.address,
+            allowed_origins: self.allowed_origins.clone(),
+            allowed_methods: self.allowed_methods.clone(),
+            allowed_headers: self.allowed_headers.clone(),
+            cors_allow_credentials: self.cors_allow_credentials,
+            admin_tokens: self.admin_tokens.clone(),
+            helper_tokens: self.helper_tokens.clone(),
+            cooldown_secs: self.cooldown_secs,
+            max_groups_per_voice_channel: self.max_groups_per_voice_channel,
+            max_queue_size: self.max_queue_size,
+            allowed_groups: self.allowed_groups.clone(),
+            denied_groups: self.denied_groups.clone(),
+            base_path: self.base_path.clone(),
+            drain_timeout_secs: self.drain_timeout_secs,
+            statsd_addr: self.statsd_addr,
+            statsd_interval_secs: self.statsd_interval_secs,
+            log_format: self.log_format,
+            log_level: self.log_level,
+            event_capacity: self.event_capacity,
+            history_size: self.history_size,
+            wait_samples: self.wait_samples,
+            default_help_secs: self.default_help_secs,
+            session_window_secs: self.session_window_secs,
+            boost_requeues: self.boost_requeues,
+            front_debounce_secs: self.front_debounce_secs,
+            rate_limit_burst: self.rate_limit_burst,
+            rate_limit_interval_secs: self.rate_limit_interval_secs,
+            idempotency_ttl_secs: self.idempotency_ttl_secs,
+            response_envelope: self.response_envelope,
+            wal_path: self.wal_path.clone(),
+            persistence_path: self.persistence_path.clone(),
+            wal_checkpoint_secs: self.wal_checkpoint_secs,
+            tls_cert: self.tls_cert.clone(),
+            tls_key: self.tls_key.clone(),
+            config: self.config.clone(),
         }
     }
 }
@@ -66,8 +382,334 @@ impl Default for ServerArguments {
         Self {
             domain: "http://0.0.0.0".to_string(),
             port: 80,
+            admin_port: None,
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            allowed_origins: Vec::new(),
+            allowed_methods: Vec::new(),
+            allowed_headers: Vec::new(),
+            cors_allow_credentials: false,
+            admin_tokens: Vec::new(),
+            helper_tokens: Vec::new(),
+            cooldown_secs: 0,
+            max_groups_per_voice_channel: None,
+            max_queue_size: None,
+            allowed_groups: Vec::new(),
+            denied_groups: Vec::new(),
+            base_path: String::new(),
+            drain_timeout_secs: 30,
+            statsd_addr: None,
+            statsd_interval_secs: 10,
+            log_format: LogFormat::Pretty,
+            log_level: Level::INFO,
+            event_capacity: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            wait_samples: DEFAULT_WAIT_SAMPLES,
+            default_help_secs: DEFAULT_HELP_DURATION.as_secs(),
+            session_window_secs: None,
+            boost_requeues: false,
+            front_debounce_secs: 0,
+            rate_limit_burst: 5,
+            rate_limit_interval_secs: 2,
+            idempotency_ttl_secs: 300,
+            response_envelope: false,
+            wal_path: None,
+            persistence_path: None,
+            wal_checkpoint_secs: 60,
+            tls_cert: None,
+            tls_key: None,
+            config: None,
+        }
+    }
+}
+
+impl ServerArguments {
+    /// Parses the arguments of the process, reading the ones missing from the
+    /// command line from the `--config` file, if given.
+    pub fn parse_with_config() -> Result<Self> {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        Self::parse_from(&args).merge_config(args)
+    }
+
+    /// Reads the arguments from a config file, leaving the missing ones at
+    /// their defaults.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::merge(
+            Self::read_config(path.as_ref())?,
+            vec!["algo3_backend".into()],
+        )
+    }
+
+    /// Fills in the arguments missing from the command line they were parsed
+    /// from with the ones in the `--config` file, if given.
+    fn merge_config(self, args: Vec<OsString>) -> Result<Self> {
+        match &self.config {
+            Some(path) => Self::merge(Self::read_config(path)?, args),
+            None => Ok(self),
+        }
+    }
+
+    /// Reads the settings of a config file, as TOML unless it is named
+    /// `*.json`.
+    fn read_config(path: &Path) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) => bail!(
+                "Error reading the config file {}: {}",
+                path.display(),
+                error
+            ),
+        };
+        let settings = match path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            true => serde_json::from_str(&contents).map_err(anyhow::Error::from),
+            false => toml::from_str(&contents).map_err(anyhow::Error::from),
+        };
+        match settings {
+            Ok(settings) => Ok(settings),
+            Err(error) => bail!(
+                "Error parsing the config file {}: {}",
+                path.display(),
+                error
+            ),
+        }
+    }
+
+    /// Parses the command line along with the settings it is missing, as if
+    /// they were passed before it.
+    fn merge(
+        settings: serde_json::Map<String, serde_json::Value>,
+        args: Vec<OsString>,
+    ) -> Result<Self> {
+        let command = Self::command();
+        let matches = command.clone().try_get_matches_from(&args)?;
+        // Every argument but the ones only meaningful on the command line,
+        // keyed by its name in the config file.
+        let settable: HashMap<String, &clap::Arg> = command
+            .get_arguments()
+            .filter(|arg| arg.get_long().is_some())
+            .filter(|arg| !["help", "version", "config"].contains(&arg.get_id()))
+            .map(|arg| (arg.get_id().replace('-', "_"), arg))
+            .collect();
+        let mut merged_args: Vec<OsString> = args.iter().take(1).cloned().collect();
+        for (key, value) in settings {
+            let (arg, long) = match settable.get(&key) {
+                Some(arg) => (arg, arg.get_long().unwrap_or_default()),
+                None => {
+                    let mut known: Vec<&str> = settable.keys().map(String::as_str).collect();
+                    known.sort_unstable();
+                    bail!(
+                        "Unknown setting '{key}' in the config file, expected one of: {}",
+                        known.join(", ")
+                    );
+                }
+            };
+            if matches.value_source(arg.get_id()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let is_flag = matches!(arg.get_action(), clap::ArgAction::SetTrue);
+            let values = match value {
+                serde_json::Value::Array(values) => values,
+                value => vec![value],
+            };
+            for value in values {
+                let value = match value {
+                    serde_json::Value::Bool(true) if is_flag => {
+                        merged_args.push(format!("--{long}").into());
+                        continue;
+                    }
+                    serde_json::Value::Bool(false) if is_flag => continue,
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Number(value) => value.to_string(),
+                    value => bail!("Invalid value {value} for '{key}' in the config file"),
+                };
+                merged_args.push(format!("--{long}={value}").into());
+            }
+        }
+        merged_args.extend(args.into_iter().skip(1));
+        Ok(Self::try_parse_from(merged_args)?)
+    }
+
+    /// Builds the CORS configuration out of the arguments.
+    fn cors(&self) -> Result<warp::cors::Builder> {
+        let wildcard = self.allowed_origins.is_empty()
+            || self.allowed_origins.iter().any(|origin| origin == "*");
+        if self.cors_allow_credentials && wildcard {
+            bail!("CORS credentials can only be allowed for explicit origins, not for '*'");
+        }
+
+        let methods = match self.allowed_methods.is_empty() {
+            true => vec!["GET", "POST", "PATCH", "DELETE"],
+            false => self.allowed_methods.iter().map(String::as_str).collect(),
+        };
+        for method in &methods {
+            // Validate here since `warp` panics on malformed methods.
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                bail!("Invalid CORS method {method}");
+            }
+        }
+        let headers = match self.allowed_headers.is_empty() {
+            true => vec![
+                "authorization",
+                "content-type",
+                "idempotency-key",
+                "if-none-match",
+                "prefer",
+            ],
+            false => self.allowed_headers.iter().map(String::as_str).collect(),
+        };
+        for allowed_header in &headers {
+            if header::HeaderName::from_bytes(allowed_header.as_bytes()).is_err() {
+                bail!("Invalid CORS header {allowed_header}");
+            }
+        }
+
+        let cors = warp::cors()
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.cors_allow_credentials)
+            .expose_headers(["x-request-id"]);
+        if wildcard {
+            return Ok(cors.allow_any_origin());
+        }
+        for origin in &self.allowed_origins {
+            // Validate here since `warp` panics on malformed origins.
+            match origin.split_once("://") {
+                Some((scheme, host)) if !scheme.is_empty() && !host.is_empty() => {}
+                _ => bail!("Invalid CORS origin {origin}, expected 'scheme://host[:port]'"),
+            }
+        }
+        Ok(cors.allow_origins(self.allowed_origins.iter().map(String::as_str)))
+    }
+
+    /// Returns a filter that matches the segments of the base path.
+    fn base_path(&self) -> BoxedFilter<()> {
+        self.base_path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .fold(warp::any().boxed(), |filter, segment| {
+                filter.and(warp::path(segment.to_string())).boxed()
+            })
+    }
+
+    /// Returns the base path in its canonical form (e.g. `/algo3`, or empty).
+    fn mount_point(&self) -> String {
+        self.base_path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| format!("/{segment}"))
+            .collect()
+    }
+
+    /// Returns the listeners to start along with their ports.
+    fn listeners(&self) -> Vec<(Listener, u16)> {
+        match self.admin_port {
+            Some(admin_port) => vec![(Listener::Public, self.port), (Listener::Admin, admin_port)],
+            None => vec![(Listener::All, self.port)],
+        }
+    }
+
+    /// Returns the certificate and key to serve HTTPS with, if any.
+    fn tls(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some((cert.clone(), key.clone()))),
+            (None, None) => Ok(None),
+            (Some(_), None) => bail!("A TLS certificate was given without its key"),
+            (None, Some(_)) => bail!("A TLS key was given without its certificate"),
+        }
+    }
+
+    /// Returns the settings of the help queue.
+    fn help_queue_config(&self) -> HelpQueueConfig {
+        HelpQueueConfig {
+            cooldown: (self.cooldown_secs > 0).then(|| Duration::from_secs(self.cooldown_secs)),
+            max_groups_per_voice_channel: self.max_groups_per_voice_channel,
+            max_queue_size: self.max_queue_size,
+            allowed_groups: self.allowed_groups.iter().copied().collect(),
+            denied_groups: self.denied_groups.iter().copied().collect(),
+            event_capacity: self.event_capacity,
+            history_size: Some(self.history_size),
+            wal_path: self.wal_path.clone(),
+            persistence_path: self.persistence_path.clone(),
+            wait_samples: Some(self.wait_samples),
+            default_help_duration: Some(Duration::from_secs(self.default_help_secs)),
+            session_window: self.session_window_secs.map(Duration::from_secs),
+            boost_requeues: self.boost_requeues,
+            front_debounce: (self.front_debounce_secs > 0)
+                .then(|| Duration::from_secs(self.front_debounce_secs)),
+        }
+    }
+
+    /// Returns the admins' names indexed by their tokens.
+    fn admins(&self) -> Result<HashMap<String, String>> {
+        credentials(&self.admin_tokens, "admin")
+    }
+
+    /// Returns the helpers' names indexed by their tokens.
+    fn helpers(&self) -> Result<HashMap<String, String>> {
+        credentials(&self.helper_tokens, "helper")
+    }
+}
+
+/// Returns the names in the given `name:token` pairs indexed by their tokens.
+fn credentials(pairs: &[String], role: &str) -> Result<HashMap<String, String>> {
+    let mut names = HashMap::new();
+    for credential in pairs {
+        match credential.split_once(':') {
+            Some((name, token)) if !name.is_empty() && !token.is_empty() => {
+                names.insert(token.to_string(), name.to_string());
+            }
+            _ => bail!("Invalid {role} token, expected 'name:token'"),
         }
     }
+    Ok(names)
+}
+
+/// The maximum length of a helper's name.
+const MAX_HELPER_NAME_LENGTH: usize = 50;
+
+/// Returns the authenticated helper, or else the one named in the body,
+/// checking that its name is valid.
+fn resolve_helper(
+    authenticated_helper: Option<String>,
+    body_helper: Option<String>,
+) -> Result<String, Rejection> {
+    let helper = authenticated_helper.or(body_helper).ok_or_else(|| {
+        reject::custom(ServerError::BadRequest(
+            "The helper must be named in the body".to_string(),
+        ))
+    })?;
+    validate_helper(&helper)?;
+    Ok(helper)
+}
+
+/// Checks that the helper's name is not blank nor too long.
+fn validate_helper(helper: &str) -> Result<(), Rejection> {
+    let length = helper.trim().chars().count();
+    if length == 0 {
+        return Err(reject::custom(ServerError::BadRequest(
+            "The helper's name cannot be empty".to_string(),
+        )));
+    }
+    if length > MAX_HELPER_NAME_LENGTH {
+        return Err(reject::custom(ServerError::BadRequest(format!(
+            "The helper's name cannot be longer than {MAX_HELPER_NAME_LENGTH} characters"
+        ))));
+    }
+    Ok(())
+}
+
+/// Sets up the global logger with the given format and level.
+fn init_logging(format: LogFormat, level: Level) {
+    let subscriber = tracing_subscriber::fmt().with_max_level(level);
+    let result = match format {
+        LogFormat::Pretty => subscriber.pretty().try_init(),
+        LogFormat::Json => subscriber.json().try_init(),
+    };
+    if let Err(error) = result {
+        eprintln!("Error initializing the logger: {}", error);
+    }
 }
 
 /// A middleware to include the given item in the handler.
@@ -77,6 +719,504 @@ fn with<T: Clone + Send>(
     warp::any().map(move || item.clone())
 }
 
+/// A middleware that lets requests through only if the routes are enabled.
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            match enabled {
+                true => Ok(()),
+                false => Err(reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+/// Marks a request as in flight for as long as it is alive.
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A request being served, logged once its response is ready.
+struct AccessLog {
+    id: Uuid,
+    method: Method,
+    path: String,
+    started_at: Instant,
+}
+
+/// A middleware that starts the access log of every request.
+fn with_access_log() -> impl Filter<Extract = (AccessLog,), Error = Infallible> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .map(|method: Method, path: FullPath| AccessLog {
+            id: Uuid::new_v4(),
+            method,
+            path: path.as_str().to_string(),
+            started_at: Instant::now(),
+        })
+}
+
+/// Logs the request along with its response, and tags the response with
+/// the request's id so clients can refer to it.
+fn log_access(access: AccessLog, reply: impl Reply) -> reply::Response {
+    let mut response = reply.into_response();
+    info!(
+        target: "access",
+        request_id = %access.id,
+        method = %access.method,
+        path = access.path,
+        status = response.status().as_u16(),
+        latency_ms = access.started_at.elapsed().as_millis() as u64,
+        "Request served"
+    );
+    if let Ok(request_id) = access.id.to_string().parse() {
+        response.headers_mut().insert("x-request-id", request_id);
+    }
+    response
+}
+
+/// The most TLS handshakes a server waits on at once.
+const MAX_TLS_HANDSHAKES: usize = 64;
+
+/// How long a client has to complete its TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Binds a server for the routes that shuts down gracefully once `shutdown`
+/// resolves, over HTTPS if given a certificate and key. A panicking handler
+/// gets a `500` response instead of taking the connection down with it.
+fn serve<F, R>(
+    routes: F,
+    address: SocketAddr,
+    tls: Option<(PathBuf, PathBuf)>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(SocketAddr, BoxFuture<'static, ()>)>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let incoming = AddrIncoming::bind(&address)?;
+    let address = incoming.local_addr();
+    let server = match tls {
+        Some((cert, key)) => {
+            let acceptor = tls_acceptor(&cert, &key)?;
+            let connections = stream::unfold(incoming, |mut incoming| async move {
+                let connection =
+                    future::poll_fn(|cx| Pin::new(&mut incoming).poll_accept(cx)).await?;
+                Some((connection, incoming))
+            })
+            .map(move |connection| {
+                let acceptor = acceptor.clone();
+                async move {
+                    let handshake = acceptor.accept(connection?);
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(connection) => connection,
+                        Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+                    }
+                }
+            })
+            .buffer_unordered(MAX_TLS_HANDSHAKES)
+            .filter_map(|connection| async move {
+                match connection {
+                    Ok(connection) => Some(Ok::<_, io::Error>(connection)),
+                    Err(error) => {
+                        warn!("Error accepting a TLS connection: {}", error);
+                        None
+                    }
+                }
+            });
+            run_server(accept::from_stream(connections), routes, shutdown)
+        }
+        None => run_server(incoming, routes, shutdown),
+    };
+    Ok((address, server))
+}
+
+/// Returns an acceptor of TLS connections with the PEM certificate chain
+/// and private key at the given paths.
+fn tls_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    for path in [cert, key] {
+        if !path.is_file() {
+            bail!("Cannot read the TLS file {}", path.display());
+        }
+    }
+    let certs = rustls_pemfile::certs(&mut io::BufReader::new(fs::File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = match rustls_pemfile::private_key(&mut io::BufReader::new(fs::File::open(key)?))? {
+        Some(key) => key,
+        None => bail!("There is no private key in {}", key.display()),
+    };
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serves the routes on the incoming connections until `shutdown` resolves,
+/// turning panics in handlers into `500` responses.
+fn run_server<I, F, R>(
+    incoming: I,
+    routes: F,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> BoxFuture<'static, ()>
+where
+    I: Accept + Send + 'static,
+    I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |_: &I::Conn| {
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let mut service = service.clone();
+                catch_panic(service.call(request))
+            }))
+        }
+    });
+    let server = hyper::Server::builder(incoming)
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown);
+    async move {
+        if let Err(error) = server.await {
+            error!("Server error: {}", error);
+        }
+    }
+    .boxed()
+}
+
+/// Turns a panic while building the response into a `500`.
+async fn catch_panic(
+    response: impl Future<Output = Result<reply::Response, Infallible>>,
+) -> Result<reply::Response, Infallible> {
+    match AssertUnwindSafe(response).catch_unwind().await {
+        Ok(response) => response,
+        Err(_) => {
+            error!("Recovered from a panic in a handler");
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({"error": "Internal server error"})),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+    }
+}
+
+/// Logs panics along with their backtrace before handing them to the
+/// previous hook. The hook is process-wide, so it is only installed once.
+pub fn log_panics() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |panic| {
+            error!("{}\n{}", panic, Backtrace::force_capture());
+            previous_hook(panic);
+        }));
+    });
+}
+
+/// Resolves when the process is asked to terminate, either by Ctrl-C or by a
+/// SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+/// A middleware that extracts whether the client sent `Prefer:
+/// return=minimal`, asking writes to skip the body of their response.
+fn with_minimal_return() -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("prefer").map(|prefer: Option<String>| {
+        prefer.is_some_and(|prefer| {
+            prefer
+                .split(',')
+                .any(|preference| preference.trim() == "return=minimal")
+        })
+    })
+}
+
+/// A middleware that extracts whether the request is a CORS preflight.
+fn with_preflight() -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::header::optional::<String>(
+            "access-control-request-method",
+        ))
+        .map(|method: Method, requested_method: Option<String>| {
+            method == Method::OPTIONS && requested_method.is_some()
+        })
+}
+
+/// Turns the `200` that `warp` answers successful preflights with into a
+/// `204`, as they have no body.
+fn preflight_reply(preflight: bool, reply: impl Reply) -> reply::Response {
+    let response = reply.into_response();
+    match preflight && response.status() == StatusCode::OK {
+        true => reply::with_status(response, StatusCode::NO_CONTENT).into_response(),
+        false => response,
+    }
+}
+
+/// Replies to a write with the given body, or with `204 No Content` if the
+/// client asked for `Prefer: return=minimal`.
+fn write_reply(minimal: bool, body: &impl Serialize, status: StatusCode) -> reply::Response {
+    match minimal {
+        true => reply::with_status(reply::reply(), StatusCode::NO_CONTENT).into_response(),
+        false => reply::with_status(reply::json(body), status).into_response(),
+    }
+}
+
+/// A middleware that authenticates an admin through a bearer token and
+/// extracts its name. Every request is let through as `anonymous` if there are
+/// no admins configured.
+fn with_admin(
+    admins: Arc<HashMap<String, String>>,
+) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(
+        move |authorization: Option<String>| {
+            let admins = admins.clone();
+            async move {
+                if admins.is_empty() {
+                    return Ok("anonymous".to_string());
+                }
+                let token = authorization
+                    .as_deref()
+                    .and_then(|authorization| authorization.strip_prefix("Bearer "));
+                match token.and_then(|token| admins.get(token)) {
+                    Some(admin) => Ok(admin.clone()),
+                    None => Err(reject::custom(ServerError::Unauthorized)),
+                }
+            }
+        },
+    )
+}
+
+/// A middleware that authenticates a helper through a bearer token and
+/// extracts its name. Nothing is extracted if there are no helpers
+/// configured, leaving helpers to name themselves.
+fn with_helper(
+    helpers: Arc<HashMap<String, String>>,
+) -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(
+        move |authorization: Option<String>| {
+            let helpers = helpers.clone();
+            async move {
+                if helpers.is_empty() {
+                    return Ok(None);
+                }
+                let token = authorization
+                    .as_deref()
+                    .and_then(|authorization| authorization.strip_prefix("Bearer "));
+                match token.and_then(|token| helpers.get(token)) {
+                    Some(helper) => Ok(Some(helper.clone())),
+                    None => Err(reject::custom(ServerError::Unauthorized)),
+                }
+            }
+        },
+    )
+}
+
+/// Converts the server's rejections into replies, letting `warp` handle the
+/// rest.
+async fn handle_rejection(rejection: Rejection) -> Result<reply::Response, Rejection> {
+    match rejection.find::<ServerError>() {
+        Some(ServerError::Unauthorized) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({"error": "Unauthorized"})),
+            StatusCode::UNAUTHORIZED,
+        )
+        .into_response()),
+        Some(ServerError::BadRequest(message)) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({ "error": message })),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response()),
+        Some(ServerError::Conflict(message)) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({ "error": message })),
+            StatusCode::CONFLICT,
+        )
+        .into_response()),
+        Some(ServerError::Request(message)) => {
+            error!("Error handling a request: {}", message);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({ "error": message })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+        Some(ServerError::Validation(validation)) => {
+            let status = match validation.code {
+                ValidationCode::NotAllowed => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            Ok(reply::with_status(reply::json(validation), status).into_response())
+        }
+        Some(ServerError::Queue(HelpQueueError::LockPoisoned)) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({"error": HelpQueueError::LockPoisoned.to_string()})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+        Some(ServerError::Queue(queue_error)) => {
+            let (field, code, status) = match queue_error {
+                HelpQueueError::AlreadyQueued(_) => {
+                    ("group", ValidationCode::AlreadyQueued, StatusCode::CONFLICT)
+                }
+                HelpQueueError::NotQueued(_) => {
+                    ("group", ValidationCode::NotQueued, StatusCode::NOT_FOUND)
+                }
+                HelpQueueError::NotClaimed(_) => {
+                    ("group", ValidationCode::NotClaimed, StatusCode::CONFLICT)
+                }
+                HelpQueueError::ClaimedByOther(_) => {
+                    ("group", ValidationCode::NotAllowed, StatusCode::FORBIDDEN)
+                }
+                HelpQueueError::NotInVoiceChannel(_) => (
+                    "voice_channel",
+                    ValidationCode::NotQueued,
+                    StatusCode::NOT_FOUND,
+                ),
+                HelpQueueError::NeverHelped(_) => {
+                    ("group", ValidationCode::NotQueued, StatusCode::NOT_FOUND)
+                }
+                HelpQueueError::HelperAlreadyRegistered(_) => (
+                    "helper",
+                    ValidationCode::AlreadyQueued,
+                    StatusCode::CONFLICT,
+                ),
+                HelpQueueError::HelperNotRegistered(_) => {
+                    ("helper", ValidationCode::NotQueued, StatusCode::NOT_FOUND)
+                }
+                _ => ("group", ValidationCode::EmptyQueue, StatusCode::NOT_FOUND),
+            };
+            Ok(reply::with_status(
+                reply::json(&ValidationError::new(field, code, queue_error.to_string())),
+                status,
+            )
+            .into_response())
+        }
+        Some(ServerError::RateLimited(rate_limited)) => {
+            let retry_after = rate_limited.retry_after.as_secs_f64().ceil() as u64;
+            Ok(reply::with_header(
+                reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "error": rate_limited.to_string(),
+                        "field": "group",
+                        "code": ValidationCode::RateLimited,
+                        "retry_after_secs": retry_after,
+                    })),
+                    StatusCode::TOO_MANY_REQUESTS,
+                ),
+                header::RETRY_AFTER,
+                retry_after,
+            )
+            .into_response())
+        }
+        Some(ServerError::Cooldown(cooldown)) => {
+            let retry_after = cooldown.remaining.as_secs_f64().ceil() as u64;
+            Ok(reply::with_header(
+                reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "error": cooldown.to_string(),
+                        "field": "group",
+                        "code": ValidationCode::Cooldown,
+                        "retry_after_secs": retry_after,
+                    })),
+                    StatusCode::TOO_MANY_REQUESTS,
+                ),
+                header::RETRY_AFTER,
+                retry_after,
+            )
+            .into_response())
+        }
+        _ => {
+            if rejection.is_not_found() {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({"error": "Not found"})),
+                    StatusCode::NOT_FOUND,
+                )
+                .into_response())
+            } else if rejection.find::<reject::PayloadTooLarge>().is_some() {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({"error": "The body is too large"})),
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                )
+                .into_response())
+            } else if rejection.find::<reject::UnsupportedMediaType>().is_some() {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({"error": "The body must be JSON"})),
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                )
+                .into_response())
+            } else if let Some(error) = rejection.find::<BodyDeserializeError>() {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({ "error": error.to_string() })),
+                    StatusCode::BAD_REQUEST,
+                )
+                .into_response())
+            } else if rejection.find::<reject::MethodNotAllowed>().is_some() {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({"error": "Method not allowed"})),
+                    StatusCode::METHOD_NOT_ALLOWED,
+                )
+                .into_response())
+            } else {
+                Err(rejection)
+            }
+        }
+    }
+}
+
+/// Wraps a JSON response in a `{"data": ..., "error": ...}` envelope if
+/// enabled, with the body as `data` on success and as `error` otherwise.
+async fn envelope(reply: impl Reply, enabled: bool) -> reply::Response {
+    let response = reply.into_response();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if !enabled || !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body: serde_json::Value = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+        Err(error) => {
+            error!("Error reading the response body: {}", error);
+            serde_json::Value::Null
+        }
+    };
+    let wrapped = match parts.status.is_success() {
+        true => serde_json::json!({"data": body, "error": null}),
+        false => serde_json::json!({"data": null, "error": body}),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    reply::Response::from_parts(parts, Body::from(wrapped.to_string()))
+}
+
 /// A server for the help queue.
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -84,134 +1224,3409 @@ pub struct WebServer {
     help_queue: Arc<HelpQueue>,
     runtime: tokio::runtime::Runtime,
     args: ServerArguments,
+    /// Where each listener is bound.
+    addresses: Vec<SocketAddr>,
+    /// Stops the servers when sent to, taken by the first `shutdown`.
+    shutdown_sender: Mutex<Option<oneshot::Sender<()>>>,
+    servers: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl WebServer {
-    /// Initializes a new instance of the server.
+    /// Initializes a new instance of the server, serving in the background
+    /// until the process is asked to terminate or `shutdown` is called.
     pub fn start(args: ServerArguments) -> Result<Self> {
+        init_logging(args.log_format, args.log_level);
+
         // Initialize a runtime.
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .thread_stack_size(8 * 1024 * 1024)
             .build()?;
 
-        let help_queue = match HelpQueue::new() {
-            Ok(help_queue) => help_queue,
-            Err(error) => bail!(error.to_string()),
-        };
+        let help_queue =
+            match HelpQueue::with_config(args.help_queue_config(), Box::new(SystemClock)) {
+                Ok(help_queue) => help_queue,
+                Err(error) => bail!(error.to_string()),
+            };
 
         let serve_args = args.clone();
         let queue = help_queue.clone();
-        // Initialize the server.
-        runtime.block_on(async move {
-            let _ = Self::start_server(queue, serve_args).await;
-        });
+        let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+        let shutdown = async move {
+            tokio::select! {
+                _ = shutdown_signal() => {}
+                _ = shutdown => {}
+            }
+        }
+        .shared();
+        // Initialize a server per listener, all sharing the same state.
+        let (addresses, servers) = runtime.block_on(async move {
+            let audit_log = AuditLog::new();
+            if let Some(statsd_addr) = serve_args.statsd_addr {
+                tokio::spawn(metrics::export_statsd(
+                    queue.clone(),
+                    statsd_addr,
+                    Duration::from_secs(serve_args.statsd_interval_secs),
+                ));
+            }
+            if serve_args.front_debounce_secs > 0 {
+                tokio::spawn(events::announce_front_periodically(
+                    queue.clone(),
+                    Duration::from_secs(1),
+                ));
+            }
+            if serve_args.wal_path.is_some() {
+                tokio::spawn(wal::checkpoint_periodically(
+                    queue.clone(),
+                    Duration::from_secs(serve_args.wal_checkpoint_secs),
+                ));
+            }
+            let mut addresses = Vec::new();
+            let mut servers = Vec::new();
+            for (listener, port) in serve_args.listeners() {
+                let (address, server) = Self::start_server(
+                    queue.clone(),
+                    audit_log.clone(),
+                    &serve_args,
+                    listener,
+                    port,
+                    shutdown.clone(),
+                )?;
+                addresses.push(address);
+                servers.push(server);
+            }
+            Ok::<_, anyhow::Error>((addresses, servers))
+        })?;
 
         Ok(Self {
             help_queue,
             runtime,
             args,
+            addresses,
+            shutdown_sender: Mutex::new(Some(shutdown_sender)),
+            servers: Mutex::new(servers),
         })
     }
 
-    fn start_server(help_queue: Arc<HelpQueue>, args: ServerArguments) -> JoinHandle<()> {
-        // Prepare the list of routes.
-        let routes = Self::routes(help_queue);
-        tokio::spawn(async move {
-            // Start the server.
-            println!("\n🌐 Server is running at {}:{}\n", args.domain, args.port);
-            warp::serve(routes).run(([0, 0, 0, 0], args.port)).await;
-        })
+    /// Returns where each listener is bound, in the order of `listeners`.
+    pub fn addresses(&self) -> &[SocketAddr] {
+        &self.addresses
+    }
+
+    /// Stops the servers as if the process was asked to terminate. Calling
+    /// it more than once has no further effect.
+    pub fn shutdown(&self) -> Result<()> {
+        match self.shutdown_sender.lock() {
+            Ok(mut shutdown_sender) => {
+                if let Some(shutdown_sender) = shutdown_sender.take() {
+                    let _ = shutdown_sender.send(());
+                }
+                Ok(())
+            }
+            Err(error) => bail!(error.to_string()),
+        }
+    }
+
+    /// Blocks until every server stopped and their sockets are closed, then
+    /// checkpoints the help queue.
+    pub fn wait(&self) -> Result<()> {
+        let servers = match self.servers.lock() {
+            Ok(mut servers) => std::mem::take(&mut *servers),
+            Err(error) => bail!(error.to_string()),
+        };
+        self.runtime.block_on(async {
+            for server in servers {
+                let _ = server.await;
+            }
+        });
+        if let Err(error) = self.help_queue.checkpoint() {
+            warn!("Error checkpointing the help queue on shutdown: {}", error);
+        }
+        Ok(())
+    }
+
+    /// Starts serving in the background until the `shutdown` future resolves.
+    /// On shutdown, new connections are refused and in-flight requests are
+    /// given the drain timeout to finish before being dropped.
+    fn start_server(
+        help_queue: Arc<HelpQueue>,
+        audit_log: Arc<AuditLog>,
+        args: &ServerArguments,
+        listener: Listener,
+        port: u16,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(SocketAddr, JoinHandle<()>)> {
+        // Prepare the list of routes, keeping count of the in-flight requests.
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let counter = in_flight.clone();
+        let ready = Arc::new(watch::Sender::new(true));
+        let routes = warp::any()
+            .map(move || InFlight::new(counter.clone()))
+            .and(Self::routes(
+                help_queue,
+                audit_log,
+                args,
+                listener,
+                ready.clone(),
+            )?)
+            .map(|_in_flight: InFlight, reply| reply);
+
+        // Bind the server, a port 0 lets the OS pick a free one.
+        let (draining_sender, draining) = oneshot::channel();
+        let address = SocketAddr::new(args.address, port);
+        let (address, server) = serve(routes, address, args.tls()?, async move {
+            shutdown.await;
+            ready.send_replace(false);
+            let _ = draining_sender.send(());
+        })?;
+        info!(
+            "🌐 Server is running at {}:{} (listening on {}, {:?} routes)",
+            args.domain,
+            address.port(),
+            address,
+            listener
+        );
+
+        // Start the server.
+        let drain_timeout = Duration::from_secs(args.drain_timeout_secs);
+        Ok((
+            address,
+            tokio::spawn(async move {
+                tokio::pin!(server);
+                tokio::select! {
+                    _ = &mut server => return,
+                    _ = draining => {}
+                }
+                let pending = in_flight.load(Ordering::SeqCst);
+                info!("Shutting down, draining {} in-flight requests", pending);
+                match tokio::time::timeout(drain_timeout, server).await {
+                    Ok(()) => info!("Drained {} in-flight requests", pending),
+                    Err(_) => warn!(
+                        "Drain timed out, dropping {} in-flight requests",
+                        in_flight.load(Ordering::SeqCst)
+                    ),
+                }
+            }),
+        ))
     }
 
     fn routes(
         help_queue: Arc<HelpQueue>,
-    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-        // GET /api/discord/v1/next
-        let next = warp::get()
+        audit_log: Arc<AuditLog>,
+        args: &ServerArguments,
+        listener: Listener,
+        ready: Arc<watch::Sender<bool>>,
+    ) -> Result<impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone> {
+        let cors = args.cors()?;
+        let admins = Arc::new(args.admins()?);
+        let helpers = Arc::new(args.helpers()?);
+        let idempotency_keys = IdempotencyKeys::new(Duration::from_secs(args.idempotency_ttl_secs));
+        let rate_limiter = RateLimiter::new(
+            args.rate_limit_burst,
+            Duration::from_secs(args.rate_limit_interval_secs),
+        );
+
+        // POST /api/discord/v1/next
+        let next = warp::post()
             .and(warp::path!("api" / "discord" / "v1" / "next"))
-            .and(warp::body::content_length_limit(64))
+            .and(warp::body::content_length_limit(4 * 1024))
             .and(warp::body::json())
+            .and(with_helper(helpers.clone()))
             .and(with(help_queue.clone()))
             .and_then(Self::next);
 
+        // POST /api/discord/v1/claim
+        let claim = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "claim"))
+            .and(warp::body::content_length_limit(4 * 1024))
+            .and(warp::body::json())
+            .and(with_helper(helpers.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::claim);
+
+        // POST /api/discord/v1/complete/{group}
+        let complete = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "complete" / u16))
+            .and(warp::body::content_length_limit(4 * 1024))
+            .and(warp::body::json())
+            .and(with_helper(helpers.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::complete);
+
+        // POST /api/discord/v1/provide/{group}
+        let provide = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "provide" / u16))
+            .and(warp::body::content_length_limit(4 * 1024))
+            .and(warp::body::json())
+            .and(with_helper(helpers.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::provide);
+
         // POST /api/discord/v1/dismiss_help
-        let dismiss_help = warp::get()
+        let dismiss_help = warp::post()
             .and(warp::path!("api" / "discord" / "v1" / "dismiss_help"))
-            .and(warp::body::content_length_limit(2))
+            .and(warp::body::content_length_limit(64))
             .and(warp::body::json())
+            .and(with_minimal_return())
             .and(with(help_queue.clone()))
+            .and(with(rate_limiter.clone()))
             .and_then(Self::dismiss_help);
 
+        // DELETE /api/discord/v1/dismiss_by_channel/{voice_channel}
+        let dismiss_by_channel = warp::delete()
+            .and(warp::path!(
+                "api" / "discord" / "v1" / "dismiss_by_channel" / u64
+            ))
+            .and(with_minimal_return())
+            .and(with(help_queue.clone()))
+            .and_then(Self::dismiss_by_channel);
+
+        // POST /api/discord/v1/requeue/{group}
+        let requeue = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "requeue" / u16))
+            .and(with_helper(helpers))
+            .and(with(help_queue.clone()))
+            .and_then(Self::requeue);
+
         // POST /api/discord/v1/enqueue_help
         let request_help = warp::post()
             .and(warp::path!("api" / "discord" / "v1" / "enqueue_help"))
             .and(warp::body::content_length_limit(10 * 1024 * 1024))
             .and(warp::body::json())
+            .and(warp::header::optional::<String>("idempotency-key"))
+            .and(with_minimal_return())
             .and(with(help_queue.clone()))
+            .and(with(idempotency_keys))
+            .and(with(rate_limiter))
+            .and(with(args.mount_point()))
             .and_then(Self::request_help);
 
+        // POST /api/discord/v1/enqueue_many
+        let request_help_many = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "enqueue_many"))
+            .and(warp::body::content_length_limit(10 * 1024 * 1024))
+            .and(warp::body::json())
+            .and(with_minimal_return())
+            .and(with(help_queue.clone()))
+            .and_then(Self::request_help_many);
+
         // PATCH /api/discord/v1/clear_help_queue
         let clear_queue = warp::patch()
             .and(warp::path!("api" / "discord" / "v1" / "clear_help_queue"))
+            .and(warp::query::<ClearOptions>())
+            .and(with_admin(admins.clone()))
             .and(with(help_queue.clone()))
+            .and(with(audit_log.clone()))
             .and_then(Self::clear_help_queue);
 
+        // POST /api/discord/v1/drain
+        let drain = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "drain"))
+            .and(with_admin(admins.clone()))
+            .and(with(help_queue.clone()))
+            .and(with(audit_log.clone()))
+            .and_then(Self::drain);
+
         // GET /api/discord/v1/help_queue
         let get_help_queue = warp::get()
             .and(warp::path!("api" / "discord" / "v1" / "help_queue"))
-            .and(with(help_queue))
+            .and(warp::query::<HelpQueueOptions>())
+            .and(warp::header::optional::<String>("if-none-match"))
+            .and(with(help_queue.clone()))
             .and_then(Self::get_help_queue);
 
-        // Return the list of routes.
-        next.or(dismiss_help)
-            .or(request_help)
-            .or(clear_queue)
-            .or(get_help_queue)
+        // GET /api/discord/v1/help_queue.ndjson
+        let get_help_queue_ndjson = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "help_queue.ndjson"))
+            .and(with(help_queue.clone()))
+            .and_then(Self::get_help_queue_ndjson);
+
+        // GET /api/discord/v1/ws
+        let watch_help_queue = warp::path!("api" / "discord" / "v1" / "ws")
+            .and(warp::ws())
+            .and(with(help_queue.clone()))
+            .and(with(ready.clone()))
+            .map(
+                |ws: Ws, help_queue: Arc<HelpQueue>, ready: Arc<watch::Sender<bool>>| {
+                    let ready = ready.subscribe();
+                    ws.on_upgrade(move |socket| Self::watch_help_queue(socket, help_queue, ready))
+                },
+            );
+
+        // GET /api/discord/v1/peek
+        let peek = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "peek"))
+            .and(with(help_queue.clone()))
+            .and_then(Self::peek);
+
+        // GET /api/discord/v1/status/{group}
+        let get_status = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "status" / u16))
+            .and(with(help_queue.clone()))
+            .and_then(Self::get_status);
+
+        // GET /api/discord/v1/wait/{group}
+        let get_wait = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "wait" / u16))
+            .and(with(help_queue.clone()))
+            .and_then(Self::get_wait);
+
+        // GET /api/discord/v1/audit
+        let get_audit = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "audit"))
+            .and(with_admin(admins.clone()))
+            .and(with(audit_log.clone()))
+            .and_then(Self::get_audit);
+
+        // GET /api/discord/v1/history
+        let get_history = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "history"))
+            .and(with_admin(admins.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::get_history);
+
+        // GET /api/discord/v1/timeline/{group}
+        let get_timeline = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "timeline" / u16))
+            .and(with_admin(admins.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::get_timeline);
+
+        // GET /api/discord/v1/export
+        let export = warp::get()
+            .and(warp::path!("api" / "discord" / "v1" / "export"))
+            .and(with_admin(admins.clone()))
+            .and(with(help_queue.clone()))
+            .and_then(Self::export);
+
+        // POST /api/discord/v1/import
+        let import = warp::post()
+            .and(warp::path!("api" / "discord" / "v1" / "import"))
+            .and(with_admin(admins))
+            .and(warp::body::content_length_limit(10 * 1024 * 1024))
+            .and(warp::body::json())
+            .and(with(help_queue))
+            .and(with(audit_log))
+            .and_then(Self::import);
+
+        // GET /api/health
+        let health = warp::get()
+            .and(warp::path!("api" / "health"))
+            .and_then(Self::health);
+
+        // GET /api/ready
+        let ready = warp::get()
+            .and(warp::path!("api" / "ready"))
+            .and(with(ready))
+            .and_then(Self::ready);
+
+        // Return the list of routes served by the listener, mounted under the
+        // base path. Every listener answers the probes.
+        let public_routes = enabled(listener != Listener::Admin)
+            .and(
+                next.or(claim)
+                    .or(complete)
+                    .or(provide)
+                    .or(dismiss_help)
+                    .or(dismiss_by_channel)
+                    .or(requeue)
+                    .or(request_help)
+                    .or(request_help_many)
+                    .or(get_help_queue)
+                    .or(get_help_queue_ndjson)
+                    .or(watch_help_queue)
+                    .or(peek)
+                    .or(get_status)
+                    .or(get_wait),
+            )
+            // Boxing the routes keeps their type, and building it, small.
+            .boxed();
+        let admin_routes = enabled(listener != Listener::Public)
+            .and(
+                clear_queue
+                    .or(drain)
+                    .or(get_audit)
+                    .or(get_history)
+                    .or(get_timeline)
+                    .or(export)
+                    .or(import),
+            )
+            .boxed();
+        Ok(with_access_log()
+            .and(
+                with_preflight()
+                    .and(
+                        args.base_path()
+                            .and(health.or(ready).or(public_routes).or(admin_routes))
+                            .recover(handle_rejection)
+                            .and(with(args.response_envelope))
+                            .then(envelope)
+                            .with(cors),
+                    )
+                    .map(preflight_reply),
+            )
+            .map(log_access))
     }
 
-    /// Returns the next group in the help queue.
-    async fn next(helper: String, help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
-        let (group, voice_channel) = help_queue.next(helper).await.or_reject()?;
+    /// Tells that the server is alive. It does not touch the help queue, so
+    /// a busy queue does not fail the probe.
+    async fn health() -> Result<impl Reply, Rejection> {
         Ok(reply::with_status(
-            reply::json(&serde_json::json!({"group": group, "voice_channel": voice_channel})),
+            reply::json(&serde_json::json!({"status": "ok"})),
             StatusCode::OK,
         ))
     }
 
-    /// Removes the dismisser from the help queue.
-    async fn dismiss_help(
-        dismisser: u16,
+    /// Tells whether the server takes new requests, which it stops doing
+    /// once it starts shutting down.
+    async fn ready(ready: Arc<watch::Sender<bool>>) -> Result<impl Reply, Rejection> {
+        match *ready.borrow() {
+            true => Ok(reply::with_status(
+                reply::json(&serde_json::json!({"status": "ready"})),
+                StatusCode::OK,
+            )),
+            false => Ok(reply::with_status(
+                reply::json(&serde_json::json!({"status": "shutting_down"})),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )),
+        }
+    }
+
+    /// Returns the next group in the help queue that the helper did not ask
+    /// to skip.
+    async fn next(
+        request: NextRequest,
+        authenticated_helper: Option<String>,
         help_queue: Arc<HelpQueue>,
     ) -> Result<impl Reply, Rejection> {
-        let (group, voice_channel) = help_queue.dismiss(dismisser).await.or_reject()?;
+        let helper = resolve_helper(authenticated_helper, request.helper)?;
+        let (group, voice_channel, wait) = help_queue
+            .next_skipping(helper.clone(), &request.skip)
+            .await
+            .or_reject()?;
         Ok(reply::with_status(
-            reply::json(&serde_json::json!({"group": group, "voice_channel": voice_channel})),
+            reply::json(&serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "wait_secs": wait.as_secs(),
+                "helper": helper,
+            })),
             StatusCode::OK,
         ))
     }
 
-    /// Pushes a requester to the help queue.
-    async fn request_help(
-        requester: Requester,
+    /// Marks the next unclaimed group as being helped by the helper, who
+    /// completes it once done.
+    async fn claim(
+        request: HelperRequest,
+        authenticated_helper: Option<String>,
         help_queue: Arc<HelpQueue>,
     ) -> Result<impl Reply, Rejection> {
-        help_queue
-            .enqueue(requester.group, requester.voice_channel)
-            .await
-            .or_reject()?;
-        Ok(reply::with_status(reply::reply(), StatusCode::OK))
+        let helper = resolve_helper(authenticated_helper, request.helper)?;
+        let (group, voice_channel) = help_queue.claim(&helper).await.or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "helper": helper,
+            })),
+            StatusCode::OK,
+        ))
     }
 
-    /// Clears the help queue.
-    async fn clear_help_queue(help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
-        help_queue.clear().await.or_reject()?;
-        Ok(reply::with_status(reply::reply(), StatusCode::OK))
+    /// Removes a claimed group from the help queue, as helped by the helper
+    /// who claimed it.
+    async fn complete(
+        group: u16,
+        request: HelperRequest,
+        authenticated_helper: Option<String>,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let helper = resolve_helper(authenticated_helper, request.helper)?;
+        let (group, voice_channel, wait) = help_queue.complete(group, &helper).await.or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "wait_secs": wait.as_secs(),
+                "helper": helper,
+            })),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Helps the given group out of order.
+    async fn provide(
+        group: u16,
+        request: HelperRequest,
+        authenticated_helper: Option<String>,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let helper = resolve_helper(authenticated_helper, request.helper)?;
+        let (group, voice_channel, wait) =
+            help_queue.provide_to(group, &helper).await.or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "wait_secs": wait.as_secs(),
+                "helper": helper,
+            })),
+            StatusCode::OK,
+        ))
     }
 
-    /// Returns the help queue in order.
-    async fn get_help_queue(help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
-        let queue: Vec<u16> = help_queue.sorted().or_reject()?.collect();
-        Ok(reply::with_status(reply::json(&queue), StatusCode::OK))
+    /// Removes the dismisser from the help queue.
+    async fn dismiss_help(
+        dismisser: u16,
+        minimal: bool,
+        help_queue: Arc<HelpQueue>,
+        rate_limiter: Arc<RateLimiter>,
+    ) -> Result<impl Reply, Rejection> {
+        rate_limiter.check(dismisser).or_reject()?;
+        let (group, voice_channel, wait) = help_queue.dismiss(dismisser).await.or_reject()?;
+        Ok(write_reply(
+            minimal,
+            &serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "wait_secs": wait.as_secs(),
+            }),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Removes the group sitting in the voice channel from the help queue,
+    /// responding with the group that was removed.
+    async fn dismiss_by_channel(
+        voice_channel: u64,
+        minimal: bool,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let (group, voice_channel, wait) = help_queue
+            .remove_by_voice_channel(voice_channel)
+            .await
+            .or_reject()?;
+        Ok(write_reply(
+            minimal,
+            &serde_json::json!({
+                "group": group,
+                "voice_channel": voice_channel,
+                "wait_secs": wait.as_secs(),
+            }),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Moves a queued group to the back of the help queue, responding with
+    /// where it now stands. An authenticated helper cannot requeue a group
+    /// another helper claimed.
+    async fn requeue(
+        group: u16,
+        authenticated_helper: Option<String>,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let position = help_queue
+            .requeue(group, authenticated_helper.as_deref())
+            .await
+            .or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "group": group,
+                "position": position,
+            })),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Pushes a requester to the help queue, responding with where it stands.
+    /// A retried request with the same `Idempotency-Key` gets the original
+    /// response instead of enqueueing the group again.
+    async fn request_help(
+        request: EnqueueRequest,
+        idempotency_key: Option<String>,
+        minimal: bool,
+        help_queue: Arc<HelpQueue>,
+        idempotency_keys: Arc<IdempotencyKeys<(Requester, Option<usize>)>>,
+        rate_limiter: Arc<RateLimiter>,
+        mount_point: String,
+    ) -> Result<impl Reply, Rejection> {
+        let requester = request.requester;
+        let idempotency_key = idempotency_key.or(request.idempotency_key);
+        // The key is reserved before enqueueing, so a retry sent while the
+        // first request is still being handled is not enqueued twice.
+        let original = match &idempotency_key {
+            Some(key) => idempotency_keys
+                .get_or_insert(key.clone(), (requester.clone(), None))
+                .or_reject()?,
+            None => None,
+        };
+        // A retry gets the position the group was given the first time.
+        let position = match original {
+            Some((original, _)) if original != requester => {
+                return Err(reject::custom(ServerError::BadRequest(
+                    "The idempotency key was already used for another request".to_string(),
+                )));
+            }
+            Some((_, Some(position))) => position,
+            Some((_, None)) => {
+                return Err(reject::custom(ServerError::Conflict(
+                    "A request with the idempotency key is still in progress".to_string(),
+                )));
+            }
+            None => {
+                // A request that fails or panics drops its reservation, so
+                // it can be retried with the same key.
+                let reservation = idempotency_key.map(|key| idempotency_keys.reservation(key));
+                let enqueued = match rate_limiter.check(requester.group) {
+                    Ok(()) => {
+                        help_queue
+                            .enqueue_with_priority(
+                                requester.group,
+                                requester.voice_channel,
+                                requester.priority,
+                            )
+                            .await
+                    }
+                    Err(error) => Err(error),
+                };
+                if let (Some(reservation), Ok(position)) = (reservation, &enqueued) {
+                    reservation
+                        .complete((requester.clone(), Some(*position)))
+                        .or_reject()?;
+                }
+                enqueued.or_reject()?
+            }
+        };
+        Ok(reply::with_header(
+            write_reply(
+                minimal,
+                &serde_json::json!({
+                    "group": requester.group,
+                    "position": position,
+                }),
+                StatusCode::CREATED,
+            ),
+            header::LOCATION,
+            format!("{mount_point}/api/discord/v1/status/{}", requester.group),
+        ))
+    }
+
+    /// Streams the help queue in order, one JSON object per line.
+    async fn get_help_queue_ndjson(help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
+        let snapshot = help_queue.snapshot().or_reject()?;
+        let lines = stream::iter(snapshot.entries).map(|entry| {
+            serde_json::to_vec(&entry).map(|mut line| {
+                line.push(b'\n');
+                line
+            })
+        });
+        Ok(reply::with_header(
+            reply::Response::new(Body::wrap_stream(lines)),
+            header::CONTENT_TYPE,
+            "application/x-ndjson",
+        ))
+    }
+
+    /// Sends the help queue in order, as `get_help_queue` does, over the
+    /// socket on connect and after every change, until the client leaves.
+    /// Upgraded sockets outlive the server's graceful shutdown, so the socket
+    /// is closed once the server stops being ready.
+    async fn watch_help_queue(
+        socket: WebSocket,
+        help_queue: Arc<HelpQueue>,
+        mut ready: watch::Receiver<bool>,
+    ) {
+        let (mut sender, mut receiver) = socket.split();
+        // Subscribe before the first send, so no change is missed in between.
+        let mut subscription = help_queue.subscribe();
+        let mut last_etag = None;
+        loop {
+            let snapshot = match help_queue.snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(error) => {
+                    warn!("Error watching the help queue: {}", error);
+                    break;
+                }
+            };
+            // Some changes come with several events, send each queue once.
+            let etag = snapshot.etag();
+            if last_etag.as_ref() != Some(&etag) {
+                let queue: Vec<u16> = snapshot.entries.iter().map(|entry| entry.group).collect();
+                let message = Message::text(serde_json::json!(queue).to_string());
+                if sender.send(message).await.is_err() {
+                    break;
+                }
+                last_etag = Some(etag);
+            }
+            tokio::select! {
+                update = subscription.recv() => {
+                    if update.is_err() {
+                        break;
+                    }
+                }
+                message = receiver.next() => match message {
+                    Some(Ok(message)) if !message.is_close() => {}
+                    _ => break,
+                },
+                _ = async { let _ = ready.wait_for(|ready| !*ready).await; } => {
+                    let _ = sender.send(Message::close()).await;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns the group that will be helped next without removing it, or
+    /// `null` if nobody is waiting.
+    async fn peek(help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
+        let next = help_queue.peek().or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&next.map(|(group, voice_channel)| {
+                serde_json::json!({
+                    "group": group,
+                    "voice_channel": voice_channel,
+                })
+            })),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Returns where a group stands in the help queue.
+    async fn get_status(group: u16, help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
+        let was_served = help_queue.was_served(group).or_reject()?;
+        match help_queue.status(group).or_reject()? {
+            Some(status) => Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "group": status.group,
+                    "voice_channel": status.voice_channel,
+                    "groups_ahead": status.groups_ahead,
+                    "wait_secs": status.wait_time.as_secs(),
+                    "was_served": was_served,
+                })),
+                StatusCode::OK,
+            )),
+            None => Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Group not in queue",
+                    "was_served": was_served,
+                })),
+                StatusCode::NOT_FOUND,
+            )),
+        }
+    }
+
+    /// Returns how many groups are ahead of the group and how long it is
+    /// estimated to wait for them.
+    async fn get_wait(group: u16, help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
+        let status = match help_queue.status(group).or_reject()? {
+            Some(status) => status,
+            None => {
+                return Ok(reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "error": format!("Group {group} is not in the queue"),
+                    })),
+                    StatusCode::NOT_FOUND,
+                ))
+            }
+        };
+        let estimated_wait = help_queue.estimated_wait(group).or_reject()?;
+        Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "position": status.groups_ahead,
+                "estimated_seconds": estimated_wait.as_secs(),
+            })),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Pushes several requesters to the help queue at once, each with the
+    /// priority it asked for.
+    async fn request_help_many(
+        requesters: Vec<Requester>,
+        minimal: bool,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let groups: Vec<u16> = requesters.iter().map(|requester| requester.group).collect();
+        let positions = help_queue
+            .enqueue_many_with_priority(
+                requesters
+                    .into_iter()
+                    .map(|requester| (requester.group, requester.voice_channel, requester.priority))
+                    .collect(),
+            )
+            .await
+            .or_reject()?;
+        let enqueued: Vec<serde_json::Value> = groups
+            .into_iter()
+            .zip(positions)
+            .map(|(group, position)| serde_json::json!({"group": group, "position": position}))
+            .collect();
+        Ok(write_reply(minimal, &enqueued, StatusCode::CREATED))
+    }
+
+    /// Clears the help queue.
+    async fn clear_help_queue(
+        options: ClearOptions,
+        admin: String,
+        help_queue: Arc<HelpQueue>,
+        audit_log: Arc<AuditLog>,
+    ) -> Result<impl Reply, Rejection> {
+        let cleared = help_queue
+            .clear_and_return(options.reason.clone())
+            .await
+            .or_reject()?;
+        let groups = cleared.iter().map(|(group, _)| *group).collect();
+        audit_log
+            .record(AuditAction::Clear, &admin, groups, options.reason)
+            .or_reject()?;
+        if !options.notify {
+            return Ok(reply::with_status(reply::reply(), StatusCode::OK).into_response());
+        }
+        let cleared: Vec<serde_json::Value> = cleared
+            .into_iter()
+            .map(|(group, voice_channel)| {
+                serde_json::json!({
+                    "group": group,
+                    "voice_channel": voice_channel,
+                })
+            })
+            .collect();
+        Ok(reply::with_status(reply::json(&cleared), StatusCode::OK).into_response())
+    }
+
+    /// Helps every group in the help queue in order, on behalf of the admin.
+    async fn drain(
+        admin: String,
+        help_queue: Arc<HelpQueue>,
+        audit_log: Arc<AuditLog>,
+    ) -> Result<impl Reply, Rejection> {
+        let drained = help_queue.drain(&admin).await.or_reject()?;
+        audit_log
+            .record(
+                AuditAction::Drain,
+                &admin,
+                drained.iter().map(|(group, _, _)| *group).collect(),
+                None,
+            )
+            .or_reject()?;
+        let served: Vec<serde_json::Value> = drained
+            .into_iter()
+            .map(|(group, voice_channel, wait)| {
+                serde_json::json!({
+                    "group": group,
+                    "voice_channel": voice_channel,
+                    "wait_secs": wait.as_secs(),
+                    "helper": admin,
+                })
+            })
+            .collect();
+        Ok(reply::with_status(reply::json(&served), StatusCode::OK))
+    }
+
+    /// Returns the help queue in order, along with its capacity and how many
+    /// groups can still join it in headers when the queue is bounded.
+    /// Responds `304 Not Modified` if the client already has this version.
+    /// The detailed view is always sent, as its waits change by the second.
+    async fn get_help_queue(
+        options: HelpQueueOptions,
+        if_none_match: Option<String>,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let mut response = if options.detailed {
+            let entries = help_queue.entries().or_reject()?;
+            reply::with_status(reply::json(&entries), StatusCode::OK).into_response()
+        } else {
+            let snapshot = help_queue.snapshot().or_reject()?;
+            let etag = snapshot.etag();
+            let not_modified = if_none_match.is_some_and(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .any(|tag| tag == "*" || tag == etag)
+            });
+            let response = if not_modified {
+                reply::with_status(reply::reply(), StatusCode::NOT_MODIFIED).into_response()
+            } else {
+                let queue: Vec<u16> = snapshot.entries.iter().map(|entry| entry.group).collect();
+                reply::with_status(reply::json(&queue), StatusCode::OK).into_response()
+            };
+            reply::with_header(response, header::ETAG, etag).into_response()
+        };
+        if let (Some(capacity), Some(remaining)) =
+            (help_queue.capacity(), help_queue.remaining().or_reject()?)
+        {
+            let headers = response.headers_mut();
+            headers.insert("x-queue-capacity", capacity.into());
+            headers.insert("x-queue-remaining", remaining.into());
+        }
+        Ok(response)
+    }
+
+    /// Returns the log of admin actions.
+    async fn get_audit(_admin: String, audit_log: Arc<AuditLog>) -> Result<impl Reply, Rejection> {
+        let entries = audit_log.entries().or_reject()?;
+        Ok(reply::with_status(reply::json(&entries), StatusCode::OK))
+    }
+
+    /// Returns the groups that left the help queue and how they left.
+    async fn get_history(
+        _admin: String,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let history = help_queue.history().or_reject()?;
+        Ok(reply::with_status(reply::json(&history), StatusCode::OK))
+    }
+
+    /// Returns the events involving the group in chronological order.
+    async fn get_timeline(
+        group: u16,
+        _admin: String,
+        help_queue: Arc<HelpQueue>,
+    ) -> Result<impl Reply, Rejection> {
+        let timeline: Vec<serde_json::Value> = help_queue
+            .group_timeline(group)
+            .or_reject()?
+            .into_iter()
+            .map(|(at, event)| serde_json::json!({"at": at, "event": event}))
+            .collect();
+        Ok(reply::with_status(reply::json(&timeline), StatusCode::OK))
+    }
+
+    /// Returns a backup of the help queue.
+    async fn export(_admin: String, help_queue: Arc<HelpQueue>) -> Result<impl Reply, Rejection> {
+        let snapshot = help_queue.snapshot().or_reject()?;
+        let stats = QueueStats {
+            length: snapshot.entries.len(),
+        };
+        Ok(reply::with_status(
+            reply::json(&QueueExport {
+                snapshot,
+                stats: Some(stats),
+            }),
+            StatusCode::OK,
+        ))
+    }
+
+    /// Restores the help queue from a backup.
+    async fn import(
+        admin: String,
+        backup: QueueExport,
+        help_queue: Arc<HelpQueue>,
+        audit_log: Arc<AuditLog>,
+    ) -> Result<impl Reply, Rejection> {
+        if let Err(error) = backup.snapshot.validate() {
+            return Err(reject::custom(ServerError::BadRequest(error.to_string())));
+        }
+        let groups = backup
+            .snapshot
+            .entries
+            .iter()
+            .map(|entry| entry.group)
+            .collect();
+        help_queue.restore(backup.snapshot).await.or_reject()?;
+        audit_log
+            .record(AuditAction::Import, &admin, groups, None)
+            .or_reject()?;
+        Ok(reply::with_status(reply::reply(), StatusCode::OK))
+    }
+}
+
+#[cfg(test)]
+mod web_server_tests {
+    use super::*;
+
+    use crate::audit_log::AuditEntry;
+    use crate::clock::MockClock;
+
+    fn routes_test(
+        args: ServerArguments,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        routes_with_queue_test(help_queue, args)
+    }
+
+    fn routes_with_queue_test(
+        help_queue: Arc<HelpQueue>,
+        args: ServerArguments,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        WebServer::routes(
+            help_queue,
+            AuditLog::new(),
+            &args,
+            Listener::All,
+            Arc::new(watch::Sender::new(true)),
+        )
+        .expect("Error building the routes")
+    }
+
+    async fn request_test(address: SocketAddr, request: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(address)
+            .await
+            .expect("Error connecting to the server");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// Returns the paths of the test CA and of the certificate and key it
+    /// signed for `localhost`.
+    fn tls_fixtures_test() -> (PathBuf, PathBuf, PathBuf) {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls");
+        (
+            directory.join("ca.pem"),
+            directory.join("cert.pem"),
+            directory.join("key.pem"),
+        )
+    }
+
+    async fn tls_request_test(address: SocketAddr, request: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (ca, _, _) = tls_fixtures_test();
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut io::BufReader::new(fs::File::open(ca).unwrap())) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = tokio::net::TcpStream::connect(address)
+            .await
+            .expect("Error connecting to the server");
+        let mut stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect("localhost".try_into().unwrap(), stream)
+            .await
+            .expect("Error completing the TLS handshake");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn get_request_test(path: &str) -> String {
+        format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+    }
+
+    fn admin_args_test() -> ServerArguments {
+        ServerArguments {
+            admin_tokens: vec!["Ivan:secret".to_string()],
+            ..ServerArguments::default()
+        }
+    }
+
+    fn credentialed_args_test() -> ServerArguments {
+        ServerArguments {
+            allowed_origins: vec!["https://dashboard.algo3.ar".to_string()],
+            cors_allow_credentials: true,
+            ..ServerArguments::default()
+        }
+    }
+
+    #[test]
+    fn test01_credentials_cannot_be_allowed_for_any_origin() {
+        let args = ServerArguments {
+            cors_allow_credentials: true,
+            ..ServerArguments::default()
+        };
+
+        assert!(args.cors().is_err());
+    }
+
+    #[test]
+    fn test02_credentials_cannot_be_allowed_for_the_wildcard_origin() {
+        let args = ServerArguments {
+            allowed_origins: vec!["*".to_string()],
+            cors_allow_credentials: true,
+            ..ServerArguments::default()
+        };
+
+        assert!(args.cors().is_err());
+    }
+
+    #[test]
+    fn test03_malformed_origins_are_rejected() {
+        let args = ServerArguments {
+            allowed_origins: vec!["dashboard.algo3.ar".to_string()],
+            ..ServerArguments::default()
+        };
+
+        assert!(args.cors().is_err());
+    }
+
+    #[tokio::test]
+    async fn test04_credentialed_request_from_an_allowed_origin_reflects_it() {
+        let routes = routes_test(credentialed_args_test());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .header(header::ORIGIN, "https://dashboard.algo3.ar")
+            .header(header::COOKIE, "session=1")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.algo3.ar"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+    }
+
+    #[tokio::test]
+    async fn test05_credentialed_request_from_a_disallowed_origin_is_forbidden() {
+        let routes = routes_test(credentialed_args_test());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .header(header::ORIGIN, "https://evil.example.com")
+            .header(header::COOKIE, "session=1")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn test06_preflight_from_an_allowed_origin_allows_credentials() {
+        let routes = routes_test(credentialed_args_test());
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/api/discord/v1/enqueue_help")
+            .header(header::ORIGIN, "https://dashboard.algo3.ar")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.algo3.ar"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+    }
+
+    #[test]
+    fn test07_malformed_admin_tokens_are_rejected() {
+        let args = ServerArguments {
+            admin_tokens: vec!["secret".to_string()],
+            ..ServerArguments::default()
+        };
+
+        assert!(args.admins().is_err());
+    }
+
+    #[tokio::test]
+    async fn test08_clearing_the_queue_produces_an_audit_entry() {
+        let routes = routes_test(admin_args_test());
+        warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/api/discord/v1/clear_help_queue")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/audit")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let entries: Vec<AuditEntry> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, AuditAction::Clear);
+        assert_eq!(entries[0].actor, "Ivan");
+        assert_eq!(entries[0].groups, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test09_admin_routes_require_a_valid_token() {
+        let routes = routes_test(admin_args_test());
+
+        let clear_response = warp::test::request()
+            .method("PATCH")
+            .path("/api/discord/v1/clear_help_queue")
+            .header(header::AUTHORIZATION, "Bearer wrong")
+            .reply(&routes)
+            .await;
+        let audit_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/audit")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(clear_response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(audit_response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test10_exported_queue_can_be_imported_into_another_server() {
+        let routes = routes_test(admin_args_test());
+        for (group, voice_channel) in [(1, 887022804183175188u64), (2, 887022804183175189)] {
+            warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .json(&serde_json::json!({"group": group, "voice_channel": voice_channel}))
+                .reply(&routes)
+                .await;
+        }
+        let export_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/export")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(export_response.status(), StatusCode::OK);
+        let backup: QueueExport = serde_json::from_slice(export_response.body()).unwrap();
+        assert_eq!(backup.stats, Some(QueueStats { length: 2 }));
+
+        let other_routes = routes_test(admin_args_test());
+        let import_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/import")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .json(&backup)
+            .reply(&other_routes)
+            .await;
+        assert_eq!(import_response.status(), StatusCode::OK);
+
+        let other_export_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/export")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&other_routes)
+            .await;
+        let other_backup: QueueExport =
+            serde_json::from_slice(other_export_response.body()).unwrap();
+        assert_eq!(other_backup, backup);
+    }
+
+    #[tokio::test]
+    async fn test11_invalid_backups_are_not_imported() {
+        let routes = routes_test(admin_args_test());
+        let backup = serde_json::json!({
+            "snapshot": {
+                "entries": [
+                    {"group": 1, "voice_channel": 887022804183175188u64, "position": 0, "enqueued_at": "2022-09-01T18:00:00Z"},
+                    {"group": 1, "voice_channel": 887022804183175189u64, "position": 1, "enqueued_at": "2022-09-01T18:01:00Z"}
+                ]
+            }
+        });
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/import")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .json(&backup)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test12_server_can_listen_on_ipv6() {
+        let args = ServerArguments {
+            address: "::1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let (address, server) = WebServer::start_server(
+            HelpQueue::new().expect("Error creating the help queue"),
+            AuditLog::new(),
+            &args,
+            Listener::All,
+            args.port,
+            std::future::pending(),
+        )
+        .expect("Error starting the server");
+        assert!(address.is_ipv6());
+
+        let response = request_test(address, &get_request_test("/api/discord/v1/help_queue")).await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("[]"));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test13_enqueueing_during_the_cooldown_reports_the_remaining_time() {
+        let args = ServerArguments {
+            cooldown_secs: 60,
+            ..ServerArguments::default()
+        };
+        let clock = MockClock::default();
+        let help_queue = HelpQueue::with_config(args.help_queue_config(), Box::new(clock.clone()))
+            .expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        help_queue.dismiss(1).await.expect("Error dismissing help");
+        clock.advance(chrono::Duration::milliseconds(15_500));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "45");
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["retry_after_secs"], 45);
+        assert_eq!(body["code"], "cooldown");
+    }
+
+    #[tokio::test]
+    async fn test14_routes_resolve_under_a_custom_base_path() {
+        let routes = routes_test(ServerArguments {
+            base_path: "/algo3/".to_string(),
+            ..ServerArguments::default()
+        });
+
+        let mounted_response = warp::test::request()
+            .method("GET")
+            .path("/algo3/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let unmounted_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(mounted_response.status(), StatusCode::OK);
+        assert_eq!(unmounted_response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test15_base_path_can_have_several_segments() {
+        let routes = routes_test(ServerArguments {
+            base_path: "courses/algo3".to_string(),
+            ..ServerArguments::default()
+        });
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/courses/algo3/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test16_shutdown_lets_in_flight_requests_finish() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+        let (address, server) = WebServer::start_server(
+            help_queue.clone(),
+            AuditLog::new(),
+            &args,
+            Listener::All,
+            args.port,
+            async {
+                let _ = shutdown.await;
+            },
+        )
+        .expect("Error starting the server");
+
+        // Start a request whose body arrives slowly.
+        let body = r#"{"group": 1, "voice_channel": 887022804183175188}"#;
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST /api/discord/v1/enqueue_help HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    &body[..10]
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        shutdown_sender.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stream.write_all(&body.as_bytes()[10..]).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 201 Created"));
+        assert_eq!(help_queue.len().unwrap(), 1);
+        assert!(tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test17_admin_routes_are_only_reachable_on_the_admin_port() {
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            admin_port: Some(0),
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let audit_log = AuditLog::new();
+        let mut addresses = HashMap::new();
+        let mut servers = Vec::new();
+        for (listener, port) in args.listeners() {
+            let (address, server) = WebServer::start_server(
+                help_queue.clone(),
+                audit_log.clone(),
+                &args,
+                listener,
+                port,
+                std::future::pending(),
+            )
+            .expect("Error starting the server");
+            addresses.insert(listener, address);
+            servers.push(server);
+        }
+        let public_address = addresses[&Listener::Public];
+        let admin_address = addresses[&Listener::Admin];
+
+        let public_audit =
+            request_test(public_address, &get_request_test("/api/discord/v1/audit")).await;
+        let admin_audit =
+            request_test(admin_address, &get_request_test("/api/discord/v1/audit")).await;
+        let public_queue = request_test(
+            public_address,
+            &get_request_test("/api/discord/v1/help_queue"),
+        )
+        .await;
+        let admin_queue = request_test(
+            admin_address,
+            &get_request_test("/api/discord/v1/help_queue"),
+        )
+        .await;
+
+        assert!(!public_audit.starts_with("HTTP/1.1 200 OK"));
+        assert!(admin_audit.starts_with("HTTP/1.1 200 OK"));
+        assert!(public_queue.starts_with("HTTP/1.1 200 OK"));
+        assert!(!admin_queue.starts_with("HTTP/1.1 200 OK"));
+        servers.iter().for_each(JoinHandle::abort);
+    }
+
+    #[tokio::test]
+    async fn test18_next_responds_with_the_wait_of_the_group() {
+        let clock = MockClock::default();
+        let help_queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(75));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/next")
+            .json(&serde_json::json!({"helper": "Ivan"}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "group": 1,
+                "voice_channel": 887022804183175188u64,
+                "wait_secs": 75,
+                "helper": "Ivan",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test19_next_rejects_blank_helper_names() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        for helper in ["", "   "] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/next")
+                .json(&serde_json::json!({ "helper": helper }))
+                .reply(&routes)
+                .await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(help_queue.len().unwrap(), 1);
+    }
+
+    #[test]
+    fn test20_helper_names_cannot_be_too_long() {
+        assert!(validate_helper(&"a".repeat(MAX_HELPER_NAME_LENGTH)).is_ok());
+        assert!(validate_helper(&"a".repeat(MAX_HELPER_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test21_many_groups_can_be_enqueued_in_one_request() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_many")
+            .json(&serde_json::json!([
+                {"group": 4, "voice_channel": 887022804183175188u64},
+                {"group": 2, "voice_channel": 887022804183175189u64}
+            ]))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{"group": 4, "position": 0}, {"group": 2, "position": 1}])
+        );
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![4, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test22_denied_groups_are_forbidden_from_enqueueing() {
+        let args = ServerArguments {
+            denied_groups: vec![0],
+            ..ServerArguments::default()
+        };
+        let help_queue =
+            HelpQueue::with_config(args.help_queue_config(), Box::new(MockClock::default()))
+                .expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 0, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["field"], "group");
+        assert_eq!(body["code"], "not_allowed");
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test23_enqueueing_points_at_the_status_of_the_group() {
+        let args = ServerArguments {
+            base_path: "/algo3/".to_string(),
+            ..ServerArguments::default()
+        };
+        let routes = routes_test(args);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/algo3/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 7, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/algo3/api/discord/v1/status/7"
+        );
+
+        let status_response = warp::test::request()
+            .method("GET")
+            .path("/algo3/api/discord/v1/status/7")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(status_response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(status_response.body()).unwrap();
+        assert_eq!(body["group"], 7);
+        assert_eq!(body["groups_ahead"], 0);
+    }
+
+    #[tokio::test]
+    async fn test24_history_tells_served_and_dismissed_groups_apart() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        help_queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+        help_queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error helping");
+        help_queue.dismiss(2).await.expect("Error dismissing");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/history")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["outcome"], "served");
+        assert_eq!(body[1]["outcome"], "dismissed");
+    }
+
+    #[tokio::test]
+    async fn test25_enqueueing_in_voice_channel_zero_is_unprocessable() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let zero_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 0}))
+            .reply(&routes)
+            .await;
+        let real_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(zero_response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = serde_json::from_slice(zero_response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "field": "voice_channel",
+                "code": "zero_voice_channel",
+                "message": "Voice channel 0 is not a valid discord id"
+            })
+        );
+        assert_eq!(real_response.status(), StatusCode::CREATED);
+        assert_eq!(help_queue.len().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test26_help_queue_can_be_streamed_as_ndjson() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            help_queue
+                .enqueue(group, 887022804183175188 + group as u64)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue.ndjson")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = std::str::from_utf8(response.body()).unwrap();
+        let groups: Vec<u64> = body
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                entry["group"].as_u64().unwrap()
+            })
+            .collect();
+        assert_eq!(groups, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test27_log_format_is_parsed_from_the_arguments() {
+        let default_args = ServerArguments::try_parse_from(["algo3_backend"]).unwrap();
+        let json_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--log-format", "json"]).unwrap();
+        let unknown_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--log-format", "xml"]);
+
+        assert_eq!(default_args.log_format, LogFormat::Pretty);
+        assert_eq!(json_args.log_format, LogFormat::Json);
+        assert!(unknown_args.is_err());
+    }
+
+    #[tokio::test]
+    async fn test28_retried_enqueues_with_the_same_idempotency_key_are_not_repeated() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            responses.push(
+                warp::test::request()
+                    .method("POST")
+                    .path("/api/discord/v1/enqueue_help")
+                    .header("idempotency-key", "2f1c7a")
+                    .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+                    .reply(&routes)
+                    .await,
+            );
+        }
+
+        assert_eq!(help_queue.len().unwrap(), 1);
+        for response in &responses {
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(
+                response.headers()[header::LOCATION],
+                "/api/discord/v1/status/1"
+            );
+        }
+        assert_eq!(responses[0].body(), responses[1].body());
+    }
+
+    #[tokio::test]
+    async fn test29_dismiss_responds_with_the_wait_of_the_group() {
+        let clock = MockClock::default();
+        let help_queue =
+            HelpQueue::with_clock(Box::new(clock.clone())).expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        clock.advance(chrono::Duration::seconds(90));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/dismiss_help")
+            .json(&1)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64, "wait_secs": 90})
+        );
+    }
+
+    #[tokio::test]
+    async fn test30_enqueueing_with_a_non_json_body_is_unsupported() {
+        let routes = routes_test(ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body("group 1, please")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["error"], "The body must be JSON");
+    }
+
+    #[tokio::test]
+    async fn test31_enqueueing_with_a_malformed_body_is_a_bad_request() {
+        let routes = routes_test(ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": "one"}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Request body deserialize error"));
+    }
+
+    #[tokio::test]
+    async fn test32_status_tells_whether_a_group_was_served() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        help_queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error helping");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/status/1")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["was_served"], true);
+    }
+
+    #[tokio::test]
+    async fn test33_unchanged_help_queue_is_not_sent_again() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let cached_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .header(header::IF_NONE_MATCH, &etag)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(cached_response.status(), StatusCode::NOT_MODIFIED);
+        assert!(cached_response.body().is_empty());
+        assert_eq!(cached_response.headers()[header::ETAG], etag.as_str());
+    }
+
+    #[tokio::test]
+    async fn test34_changed_help_queue_is_sent_with_a_new_etag() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let changed_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .header(header::IF_NONE_MATCH, &etag)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(changed_response.status(), StatusCode::OK);
+        assert_ne!(changed_response.headers()[header::ETAG], etag.as_str());
+        assert_eq!(changed_response.body(), "[1]");
+    }
+
+    #[tokio::test]
+    async fn test35_panicking_handlers_get_an_internal_server_error() {
+        let panicking = warp::path("panic").map(|| -> &'static str { panic!("Deliberate panic") });
+        let healthy = warp::path("healthy").map(|| "Still running");
+        let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+        let (address, server) = serve(
+            panicking.or(healthy),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+            None,
+            async move {
+                let _ = shutdown.await;
+            },
+        )
+        .expect("Error starting the server");
+        let server = tokio::spawn(server);
+
+        let panicked_response = request_test(address, &get_request_test("/panic")).await;
+        let healthy_response = request_test(address, &get_request_test("/healthy")).await;
+
+        assert!(panicked_response.starts_with("HTTP/1.1 500 Internal Server Error"));
+        assert!(panicked_response.ends_with(r#"{"error":"Internal server error"}"#));
+        assert!(healthy_response.starts_with("HTTP/1.1 200 OK"));
+        shutdown_sender.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test36_the_reason_to_clear_the_queue_is_audited() {
+        let routes = routes_test(admin_args_test());
+
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/api/discord/v1/clear_help_queue?reason=Session%20ended")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/audit")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .reply(&routes)
+            .await;
+        let entries: Vec<AuditEntry> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(entries[0].reason, Some("Session ended".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test37_a_helper_can_pick_a_group_out_of_order() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/provide/2")
+            .json(&serde_json::json!({"helper": "Ivan"}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["group"], 2);
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![1, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test38_rejected_enqueues_name_the_field_and_code() {
+        let args = ServerArguments {
+            max_groups_per_voice_channel: Some(1),
+            ..ServerArguments::default()
+        };
+        let help_queue =
+            HelpQueue::with_config(args.help_queue_config(), Box::new(MockClock::default()))
+                .expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+
+        let cases = [
+            (
+                serde_json::json!({"group": 2, "voice_channel": 887022804183175188u64}),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "voice_channel",
+                "voice_channel_full",
+            ),
+            (
+                serde_json::json!({"group": 1, "voice_channel": 887022804183175189u64}),
+                StatusCode::CONFLICT,
+                "group",
+                "already_queued",
+            ),
+        ];
+
+        for (request, status, field, code) in cases {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .json(&request)
+                .reply(&routes)
+                .await;
+
+            assert_eq!(response.status(), status);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(body["field"], field);
+            assert_eq!(body["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test39_bounded_help_queues_report_their_capacity() {
+        let args = ServerArguments {
+            max_queue_size: Some(3),
+            ..ServerArguments::default()
+        };
+        let help_queue =
+            HelpQueue::with_config(args.help_queue_config(), Box::new(MockClock::default()))
+                .expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let bounded_routes = routes_with_queue_test(help_queue, args);
+        let unbounded_routes = routes_test(ServerArguments::default());
+
+        let bounded_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&bounded_routes)
+            .await;
+        let unbounded_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&unbounded_routes)
+            .await;
+
+        assert_eq!(bounded_response.headers()["x-queue-capacity"], "3");
+        assert_eq!(bounded_response.headers()["x-queue-remaining"], "2");
+        assert!(!unbounded_response
+            .headers()
+            .contains_key("x-queue-capacity"));
+        assert!(!unbounded_response
+            .headers()
+            .contains_key("x-queue-remaining"));
+    }
+
+    #[tokio::test]
+    async fn test40_responses_can_be_wrapped_in_an_envelope() {
+        let mut bodies = Vec::new();
+        for response_envelope in [false, true] {
+            let help_queue = HelpQueue::new().expect("Error creating the help queue");
+            help_queue
+                .enqueue(1, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+            let routes = routes_with_queue_test(
+                help_queue,
+                ServerArguments {
+                    response_envelope,
+                    ..ServerArguments::default()
+                },
+            );
+
+            for group in [1, 2] {
+                let response = warp::test::request()
+                    .method("GET")
+                    .path(&format!("/api/discord/v1/status/{group}"))
+                    .reply(&routes)
+                    .await;
+                bodies.push(serde_json::from_slice::<serde_json::Value>(response.body()).unwrap());
+            }
+        }
+
+        let (found, not_found) = (&bodies[0], &bodies[1]);
+        assert_eq!(found["group"], 1);
+        assert_eq!(bodies[2], serde_json::json!({"data": found, "error": null}));
+        assert_eq!(
+            bodies[3],
+            serde_json::json!({"data": null, "error": not_found})
+        );
+    }
+
+    #[tokio::test]
+    async fn test41_next_can_skip_unreachable_groups() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/next")
+            .json(&serde_json::json!({"helper": "Ivan", "skip": [1]}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["group"], 2);
+        assert_eq!(help_queue.sorted().unwrap().collect::<Vec<u16>>(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test42_only_admins_can_drain_the_help_queue() {
+        let args = ServerArguments {
+            admin_tokens: vec!["ivan:secret".to_string()],
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [2, 1] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+
+        let anonymous_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/drain")
+            .reply(&routes)
+            .await;
+        let admin_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/drain")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(anonymous_response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(admin_response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(admin_response.body()).unwrap();
+        assert_eq!(body[0]["group"], 2);
+        assert_eq!(body[1]["group"], 1);
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test43_enqueueing_honors_the_return_preference() {
+        let routes = routes_test(ServerArguments::default());
+
+        let minimal_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .header("prefer", "return=minimal")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+        let representation_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .header("prefer", "return=representation")
+            .json(&serde_json::json!({"group": 2, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(minimal_response.status(), StatusCode::NO_CONTENT);
+        assert!(minimal_response.body().is_empty());
+        assert_eq!(
+            minimal_response.headers()[header::LOCATION],
+            "/api/discord/v1/status/1"
+        );
+        assert_eq!(representation_response.status(), StatusCode::CREATED);
+        let body: serde_json::Value =
+            serde_json::from_slice(representation_response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "group": 2,
+                "position": 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test44_authenticated_helpers_are_named_after_their_token() {
+        let args = ServerArguments {
+            helper_tokens: vec!["Ivan:secret".to_string()],
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+
+        let anonymous_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/provide/2")
+            .json(&serde_json::json!({"helper": "Mallory"}))
+            .reply(&routes)
+            .await;
+        let authenticated_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/provide/2")
+            .header("authorization", "Bearer secret")
+            .json(&serde_json::json!({"helper": "Mallory"}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(anonymous_response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(authenticated_response.status(), StatusCode::OK);
+        let history = help_queue.history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].helper.as_deref(), Some("Ivan"));
+    }
+
+    #[tokio::test]
+    async fn test45_the_timeline_of_a_group_can_be_retrieved() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(7, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        help_queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error helping");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/timeline/7")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let events: Vec<&serde_json::Value> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| &entry["event"]["type"])
+            .collect();
+        assert_eq!(events, vec!["enqueued", "new_front", "left"]);
+    }
+
+    #[tokio::test]
+    async fn test46_the_wait_of_a_group_can_be_estimated() {
+        let args = ServerArguments {
+            default_help_secs: 120,
+            ..ServerArguments::default()
+        };
+        let help_queue =
+            HelpQueue::with_config(args.help_queue_config(), Box::new(MockClock::default()))
+                .expect("Error creating the help queue");
+        for group in 1..=3 {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue, args);
+
+        let queued_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/wait/3")
+            .reply(&routes)
+            .await;
+        let missing_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/wait/4")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(queued_response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(queued_response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"position": 2, "estimated_seconds": 240})
+        );
+        assert_eq!(missing_response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = serde_json::from_slice(missing_response.body()).unwrap();
+        assert_eq!(body["error"], "Group 4 is not in the queue");
+    }
+
+    #[tokio::test]
+    async fn test47_blocked_groups_can_enqueue_with_high_priority() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        for request in [
+            serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}),
+            serde_json::json!({"group": 2, "voice_channel": 887022804183175188u64, "priority": "high"}),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .json(&request)
+                .reply(&routes)
+                .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn test48_the_server_can_be_shut_down_programmatically() {
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let server = WebServer::start(args).expect("Error starting the server");
+        let address = server.addresses()[0];
+
+        let response = server.runtime.block_on(request_test(
+            address,
+            &get_request_test("/api/discord/v1/help_queue"),
+        ));
+        server.shutdown().expect("Error shutting down the server");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || sender.send(server.wait()));
+        let stopped = receiver.recv_timeout(Duration::from_secs(5));
+        let reconnected = std::net::TcpStream::connect(address);
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(stopped.is_ok_and(|result| result.is_ok()));
+        assert!(reconnected.is_err());
+    }
+
+    #[tokio::test]
+    async fn test49_enqueueing_responds_with_the_position_of_the_group() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=2 {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        help_queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error helping");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 3, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, serde_json::json!({"group": 3, "position": 1}));
+    }
+
+    #[tokio::test]
+    async fn test50_preflights_allow_the_headers_the_routes_read() {
+        let routes = routes_test(ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/api/discord/v1/help_queue")
+            .header(header::ORIGIN, "http://localhost:3000")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "authorization, if-none-match",
+            )
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert!(response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .contains("GET"));
+    }
+
+    #[tokio::test]
+    async fn test51_preflights_reject_methods_that_are_not_allowed() {
+        let args = ServerArguments {
+            allowed_methods: vec!["GET".to_string()],
+            ..ServerArguments::default()
+        };
+        let routes = routes_test(args);
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/api/discord/v1/enqueue_help")
+            .header(header::ORIGIN, "http://localhost:3000")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test52_malformed_cors_methods_are_rejected() {
+        let args = ServerArguments {
+            allowed_methods: vec!["GET POST".to_string()],
+            ..ServerArguments::default()
+        };
+
+        assert!(args.cors().is_err());
+    }
+
+    #[tokio::test]
+    async fn test53_next_and_dismiss_help_take_their_body_by_post() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 12345] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let (address, server) = WebServer::start_server(
+            help_queue.clone(),
+            AuditLog::new(),
+            &args,
+            Listener::All,
+            args.port,
+            std::future::pending(),
+        )
+        .expect("Error starting the server");
+        let post_request = |path: &str, body: &str| {
+            format!(
+                "POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        };
+
+        let get_response = request_test(address, &get_request_test("/api/discord/v1/next")).await;
+        let next_response = request_test(
+            address,
+            &post_request("/api/discord/v1/next", r#"{"helper": "Ivan"}"#),
+        )
+        .await;
+        let dismiss_response = request_test(
+            address,
+            &post_request("/api/discord/v1/dismiss_help", "12345"),
+        )
+        .await;
+        server.abort();
+
+        assert!(get_response.starts_with("HTTP/1.1 405"));
+        assert!(next_response.starts_with("HTTP/1.1 200 OK"));
+        assert!(next_response.contains(r#""group":1"#));
+        assert!(dismiss_response.starts_with("HTTP/1.1 200 OK"));
+        assert!(dismiss_response.contains(r#""group":12345"#));
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test54_probes_are_served_outside_the_business_routes() {
+        let args = ServerArguments {
+            base_path: "/algo3".to_string(),
+            ..ServerArguments::default()
+        };
+        let ready = Arc::new(watch::Sender::new(true));
+        let routes = WebServer::routes(
+            HelpQueue::new().expect("Error creating the help queue"),
+            AuditLog::new(),
+            &args,
+            Listener::Admin,
+            ready.clone(),
+        )
+        .expect("Error building the routes");
+
+        let health_response = warp::test::request()
+            .method("GET")
+            .path("/algo3/api/health")
+            .reply(&routes)
+            .await;
+        let ready_response = warp::test::request()
+            .method("GET")
+            .path("/algo3/api/ready")
+            .reply(&routes)
+            .await;
+        ready.send_replace(false);
+        let shutting_down_response = warp::test::request()
+            .method("GET")
+            .path("/algo3/api/ready")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(health_response.status(), StatusCode::OK);
+        assert_eq!(health_response.body(), r#"{"status":"ok"}"#);
+        assert_eq!(ready_response.status(), StatusCode::OK);
+        assert_eq!(
+            shutting_down_response.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test55_peek_shows_the_next_group_without_helping_it() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let empty_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/peek")
+            .reply(&routes)
+            .await;
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/peek")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(empty_response.status(), StatusCode::OK);
+        assert_eq!(empty_response.body(), "null");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64})
+        );
+        assert_eq!(help_queue.len().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test56_errors_are_returned_as_json() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let args = ServerArguments {
+            base_path: "/algo3".to_string(),
+            ..ServerArguments::default()
+        };
+        let routes = routes_with_queue_test(help_queue, args);
+
+        let already_queued = warp::test::request()
+            .method("POST")
+            .path("/algo3/api/discord/v1/enqueue_help")
+            .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175188u64}))
+            .reply(&routes)
+            .await;
+        let not_queued = warp::test::request()
+            .method("POST")
+            .path("/algo3/api/discord/v1/dismiss_help")
+            .json(&2)
+            .reply(&routes)
+            .await;
+        let malformed = warp::test::request()
+            .method("POST")
+            .path("/algo3/api/discord/v1/enqueue_help")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body("{")
+            .reply(&routes)
+            .await;
+        let unmounted = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+
+        let json = |bytes: &[u8]| serde_json::from_slice::<serde_json::Value>(bytes).unwrap();
+        assert_eq!(already_queued.status(), StatusCode::CONFLICT);
+        assert_eq!(json(already_queued.body())["code"], "already_queued");
+        assert_eq!(not_queued.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            json(not_queued.body()),
+            serde_json::json!({
+                "field": "group",
+                "code": "not_queued",
+                "message": "Group 2 not in queue"
+            })
+        );
+        assert_eq!(malformed.status(), StatusCode::BAD_REQUEST);
+        assert!(json(malformed.body())["error"].is_string());
+        assert_eq!(unmounted.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            json(unmounted.body()),
+            serde_json::json!({"error": "Not found"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test57_the_help_queue_is_pushed_over_a_websocket() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        let mut client = warp::test::ws()
+            .path("/api/discord/v1/ws")
+            .handshake(routes)
+            .await
+            .expect("Error connecting to the websocket");
+
+        let on_connect = client.recv().await.unwrap();
+        help_queue
+            .enqueue(2, 887022804183175189)
+            .await
+            .expect("Error enqueueing help");
+        let after_enqueue = client.recv().await.unwrap();
+        help_queue
+            .next("Ivan".to_string())
+            .await
+            .expect("Error helping");
+        let after_next = client.recv().await.unwrap();
+
+        assert_eq!(on_connect.to_str().unwrap(), "[1]");
+        assert_eq!(after_enqueue.to_str().unwrap(), "[1,2]");
+        assert_eq!(after_next.to_str().unwrap(), "[2]");
+    }
+
+    #[tokio::test]
+    async fn test58_groups_can_be_dismissed_by_voice_channel() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(3, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/api/discord/v1/dismiss_by_channel/887022804183175188")
+            .reply(&routes)
+            .await;
+        let missing_response = warp::test::request()
+            .method("DELETE")
+            .path("/api/discord/v1/dismiss_by_channel/887022804183175188")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["group"], 3);
+        assert_eq!(missing_response.status(), StatusCode::NOT_FOUND);
+        let missing_body: serde_json::Value =
+            serde_json::from_slice(missing_response.body()).unwrap();
+        assert_eq!(missing_body["field"], "voice_channel");
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test59_the_help_queue_can_be_read_in_detail() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(4, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue?detailed=true")
+            .reply(&routes)
+            .await;
+        let plain_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue?detailed=false")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["group"], 4);
+        assert_eq!(body[0]["voice_channel"], 887022804183175188u64);
+        assert_eq!(body[0]["position"], 0);
+        assert!(body[0]["waiting_secs"].is_u64());
+        assert_eq!(plain_response.body(), "[4]");
+    }
+
+    #[tokio::test]
+    async fn test60_groups_acting_too_often_are_rate_limited() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(
+            help_queue,
+            ServerArguments {
+                rate_limit_burst: 2,
+                rate_limit_interval_secs: 30,
+                ..ServerArguments::default()
+            },
+        );
+        let enqueue = || {
+            warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .json(&serde_json::json!({"group": 5, "voice_channel": 887022804183175188u64}))
+        };
+        let dismiss = || {
+            warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/dismiss_help")
+                .json(&5)
+        };
+
+        let enqueued = enqueue().reply(&routes).await;
+        let dismissed = dismiss().reply(&routes).await;
+        let limited = enqueue().reply(&routes).await;
+
+        assert_eq!(enqueued.status(), StatusCode::CREATED);
+        assert_eq!(dismissed.status(), StatusCode::OK);
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "30");
+        let body: serde_json::Value = serde_json::from_slice(limited.body()).unwrap();
+        assert_eq!(body["code"], "rate_limited");
+    }
+
+    #[test]
+    fn test61_tls_needs_both_a_certificate_and_a_key() {
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let missing_path = std::env::temp_dir().join("algo3-missing-cert.pem");
+
+        let only_cert = WebServer::start(ServerArguments {
+            tls_cert: Some(missing_path.clone()),
+            ..args.clone()
+        });
+        let only_key = WebServer::start(ServerArguments {
+            tls_key: Some(missing_path.clone()),
+            ..args.clone()
+        });
+        let missing_files = WebServer::start(ServerArguments {
+            tls_cert: Some(missing_path.clone()),
+            tls_key: Some(missing_path),
+            ..args
+        });
+
+        assert_eq!(
+            only_cert.unwrap_err().to_string(),
+            "A TLS certificate was given without its key"
+        );
+        assert_eq!(
+            only_key.unwrap_err().to_string(),
+            "A TLS key was given without its certificate"
+        );
+        assert!(missing_files
+            .unwrap_err()
+            .to_string()
+            .starts_with("Cannot read the TLS file"));
+    }
+
+    #[tokio::test]
+    async fn test62_every_response_carries_its_request_id() {
+        let routes = routes_test(ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let other_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let rejected_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/dismiss_help")
+            .body("not json")
+            .reply(&routes)
+            .await;
+
+        let request_id = |response: &warp::http::Response<hyper::body::Bytes>| {
+            Uuid::parse_str(response.headers()["x-request-id"].to_str().unwrap()).unwrap()
+        };
+        assert_ne!(request_id(&response), request_id(&other_response));
+        assert_eq!(rejected_response.status(), StatusCode::BAD_REQUEST);
+        request_id(&rejected_response);
+    }
+
+    #[test]
+    fn test63_log_level_is_parsed_from_the_arguments() {
+        let default_args = ServerArguments::try_parse_from(["algo3_backend"]).unwrap();
+        let debug_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--log-level", "debug"]).unwrap();
+        let unknown_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--log-level", "loud"]);
+
+        assert_eq!(default_args.log_level, Level::INFO);
+        assert_eq!(debug_args.log_level, Level::DEBUG);
+        assert!(unknown_args.is_err());
+    }
+
+    fn config_test(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("algo3-config-{}-{name}", std::process::id()));
+        fs::write(&path, contents).expect("Error writing the config file");
+        path
+    }
+
+    #[test]
+    fn test64_arguments_missing_from_the_command_line_are_read_from_the_config() {
+        let path = config_test(
+            "test64.toml",
+            "port = 8080\nmax_queue_size = 30\nallowed_origins = [\"https://algo3.ar\"]\nresponse_envelope = true\n",
+        );
+        let args: Vec<OsString> = vec![
+            "algo3_backend".into(),
+            "--config".into(),
+            path.clone().into(),
+            "--port".into(),
+            "9090".into(),
+        ];
+
+        let merged = ServerArguments::try_parse_from(&args)
+            .unwrap()
+            .merge_config(args)
+            .expect("Error reading the config");
+        let from_file = ServerArguments::from_file(&path).expect("Error reading the config");
+        let _ = fs::remove_file(path);
+
+        assert_eq!(merged.port, 9090);
+        assert_eq!(merged.max_queue_size, Some(30));
+        assert_eq!(merged.allowed_origins, vec!["https://algo3.ar"]);
+        assert!(merged.response_envelope);
+        assert_eq!(from_file.port, 8080);
+        assert_eq!(from_file.history_size, DEFAULT_HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test65_config_files_can_be_json() {
+        let path = config_test(
+            "test65.json",
+            r#"{"cooldown_secs": 60, "denied_groups": [0, 99]}"#,
+        );
+
+        let args = ServerArguments::from_file(&path).expect("Error reading the config");
+        let _ = fs::remove_file(path);
+
+        assert_eq!(args.cooldown_secs, 60);
+        assert_eq!(args.denied_groups, vec![0, 99]);
+        assert!(!args.response_envelope);
+    }
+
+    #[test]
+    fn test66_unknown_settings_in_the_config_are_an_error() {
+        let path = config_test("test66.toml", "max_queue_sise = 30\n");
+
+        let error = ServerArguments::from_file(&path).unwrap_err();
+        let _ = fs::remove_file(path);
+
+        assert!(error
+            .to_string()
+            .starts_with("Unknown setting 'max_queue_sise' in the config file"));
+        assert!(error.to_string().contains("max_queue_size"));
+    }
+
+    #[tokio::test]
+    async fn test67_groups_can_be_requeued() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=3 {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/requeue/1")
+            .reply(&routes)
+            .await;
+        let missing_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/requeue/4")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), r#"{"group":1,"position":2}"#);
+        assert_eq!(missing_response.status(), StatusCode::NOT_FOUND);
+        let missing_body: serde_json::Value =
+            serde_json::from_slice(missing_response.body()).unwrap();
+        assert_eq!(missing_body["code"], "not_queued");
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![2, 3, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test68_groups_can_be_claimed_and_completed() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(3, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let claimed = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/claim")
+            .json(&serde_json::json!({"helper": "Ivan"}))
+            .reply(&routes)
+            .await;
+        let detailed = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue?detailed=true")
+            .reply(&routes)
+            .await;
+        let completed = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/complete/3")
+            .json(&serde_json::json!({"helper": "Ivan"}))
+            .reply(&routes)
+            .await;
+        let completed_again = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/complete/3")
+            .json(&serde_json::json!({"helper": "Ivan"}))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(claimed.status(), StatusCode::OK);
+        assert_eq!(
+            claimed.body(),
+            r#"{"group":3,"helper":"Ivan","voice_channel":887022804183175188}"#
+        );
+        let entries: serde_json::Value = serde_json::from_slice(detailed.body()).unwrap();
+        assert_eq!(entries[0]["helper"], "Ivan");
+        assert_eq!(completed.status(), StatusCode::OK);
+        let completed_body: serde_json::Value = serde_json::from_slice(completed.body()).unwrap();
+        assert_eq!(completed_body["helper"], "Ivan");
+        assert_eq!(completed_again.status(), StatusCode::NOT_FOUND);
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test69_clearing_can_respond_with_the_cleared_groups() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in 1..=2 {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/api/discord/v1/clear_help_queue?notify=true")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([
+                {"group": 1, "voice_channel": 887022804183175188u64},
+                {"group": 2, "voice_channel": 887022804183175188u64},
+            ])
+        );
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test70_retried_enqueues_get_their_original_position() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        let enqueue = || {
+            warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .json(&serde_json::json!({
+                    "group": 1,
+                    "voice_channel": 887022804183175188u64,
+                    "idempotency_key": "8d2e41",
+                }))
+        };
+
+        let response = enqueue().reply(&routes).await;
+        help_queue
+            .enqueue_with_priority(2, 887022804183175189, Priority::High)
+            .await
+            .expect("Error enqueueing help");
+        let retried_response = enqueue().reply(&routes).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(retried_response.status(), StatusCode::CREATED);
+        assert_eq!(response.body(), r#"{"group":1,"position":0}"#);
+        assert_eq!(retried_response.body(), response.body());
+        assert_eq!(help_queue.len().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test71_the_detailed_help_queue_is_never_answered_as_not_modified() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue, ServerArguments::default());
+        let response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue")
+            .reply(&routes)
+            .await;
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let detailed_response = warp::test::request()
+            .method("GET")
+            .path("/api/discord/v1/help_queue?detailed=true")
+            .header(header::IF_NONE_MATCH, &etag)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(detailed_response.status(), StatusCode::OK);
+        assert!(!detailed_response.headers().contains_key(header::ETAG));
+    }
+
+    #[tokio::test]
+    async fn test72_only_the_helper_who_claimed_a_group_can_complete_it() {
+        let args = ServerArguments {
+            helper_tokens: vec!["Ivan:secret".to_string(), "Ana:other".to_string()],
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(3, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        help_queue.claim("Ivan").await.expect("Error claiming");
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+        let complete = |token: Option<&str>| {
+            let request = warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/complete/3")
+                .json(&serde_json::json!({"helper": "Ivan"}));
+            match token {
+                Some(token) => request.header("authorization", format!("Bearer {token}")),
+                None => request,
+            }
+        };
+
+        let anonymous_response = complete(None).reply(&routes).await;
+        let other_helper_response = complete(Some("other")).reply(&routes).await;
+        let claimer_response = complete(Some("secret")).reply(&routes).await;
+
+        assert_eq!(anonymous_response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(other_helper_response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(claimer_response.status(), StatusCode::OK);
+        assert!(help_queue.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test73_the_session_window_is_passed_to_the_help_queue() {
+        let path = config_test("test73.toml", "session_window_secs = 7200\n");
+        let args: Vec<OsString> = vec![
+            "algo3_backend".into(),
+            "--config".into(),
+            path.clone().into(),
+        ];
+
+        let default_args = ServerArguments::try_parse_from(["algo3_backend"]).unwrap();
+        let flag_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--session-window-secs", "3600"])
+                .unwrap();
+        let config_args = ServerArguments::try_parse_from(&args)
+            .unwrap()
+            .merge_config(args)
+            .expect("Error reading the config");
+        let _ = fs::remove_file(path);
+
+        assert_eq!(default_args.help_queue_config().session_window, None);
+        assert_eq!(
+            flag_args.help_queue_config().session_window,
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            config_args.help_queue_config().session_window,
+            Some(Duration::from_secs(7200))
+        );
+    }
+
+    #[test]
+    fn test74_boosting_requeues_is_passed_to_the_help_queue() {
+        let path = config_test("test74.toml", "boost_requeues = true\n");
+        let args: Vec<OsString> = vec![
+            "algo3_backend".into(),
+            "--config".into(),
+            path.clone().into(),
+        ];
+
+        let default_args = ServerArguments::try_parse_from(["algo3_backend"]).unwrap();
+        let flag_args =
+            ServerArguments::try_parse_from(["algo3_backend", "--boost-requeues"]).unwrap();
+        let config_args = ServerArguments::try_parse_from(&args)
+            .unwrap()
+            .merge_config(args)
+            .expect("Error reading the config");
+        let _ = fs::remove_file(path);
+
+        assert!(!default_args.help_queue_config().boost_requeues);
+        assert!(flag_args.help_queue_config().boost_requeues);
+        assert!(config_args.help_queue_config().boost_requeues);
+    }
+
+    #[tokio::test]
+    async fn test75_failed_enqueues_can_be_retried_with_the_same_idempotency_key() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        help_queue
+            .enqueue(1, 887022804183175188)
+            .await
+            .expect("Error enqueueing help");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+        let enqueue = || {
+            warp::test::request()
+                .method("POST")
+                .path("/api/discord/v1/enqueue_help")
+                .header("idempotency-key", "retry")
+                .json(&serde_json::json!({"group": 1, "voice_channel": 887022804183175189u64}))
+        };
+
+        let failed_response = enqueue().reply(&routes).await;
+        help_queue.dismiss(1).await.expect("Error dismissing help");
+        let retried_response = enqueue().reply(&routes).await;
+
+        assert_eq!(failed_response.status(), StatusCode::CONFLICT);
+        assert_eq!(retried_response.status(), StatusCode::CREATED);
+        assert_eq!(
+            help_queue
+                .status(1)
+                .unwrap()
+                .map(|status| status.voice_channel),
+            Some(887022804183175189)
+        );
+    }
+
+    #[tokio::test]
+    async fn test76_groups_enqueued_in_one_request_keep_their_priority() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        let routes = routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/enqueue_many")
+            .json(&serde_json::json!([
+                {"group": 4, "voice_channel": 887022804183175188u64},
+                {"group": 2, "voice_channel": 887022804183175189u64, "priority": "high"}
+            ]))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{"group": 4, "position": 1}, {"group": 2, "position": 0}])
+        );
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![2, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test77_authenticated_helpers_need_not_name_themselves_in_the_body() {
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        let authenticated_routes = routes_with_queue_test(
+            help_queue.clone(),
+            ServerArguments {
+                helper_tokens: vec!["Ivan:secret".to_string()],
+                ..ServerArguments::default()
+            },
+        );
+        let anonymous_routes =
+            routes_with_queue_test(help_queue.clone(), ServerArguments::default());
+
+        let authenticated_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/next")
+            .header("authorization", "Bearer secret")
+            .json(&serde_json::json!({}))
+            .reply(&authenticated_routes)
+            .await;
+        let anonymous_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/next")
+            .json(&serde_json::json!({}))
+            .reply(&anonymous_routes)
+            .await;
+
+        assert_eq!(authenticated_response.status(), StatusCode::OK);
+        let body: serde_json::Value =
+            serde_json::from_slice(authenticated_response.body()).unwrap();
+        assert_eq!(body["group"], 1);
+        assert_eq!(body["helper"], "Ivan");
+        assert_eq!(anonymous_response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(help_queue.sorted().unwrap().collect::<Vec<u16>>(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test78_panicking_handlers_get_an_internal_server_error_over_tls() {
+        let panicking = warp::path("panic").map(|| -> &'static str { panic!("Deliberate panic") });
+        let healthy = warp::path("healthy").map(|| "Still running");
+        let (_, cert, key) = tls_fixtures_test();
+        let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+        let (address, server) = serve(
+            panicking.or(healthy),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+            Some((cert, key)),
+            async move {
+                let _ = shutdown.await;
+            },
+        )
+        .expect("Error starting the server");
+        let server = tokio::spawn(server);
+
+        let healthy_response = tls_request_test(address, &get_request_test("/healthy")).await;
+        let panicked_response = tls_request_test(address, &get_request_test("/panic")).await;
+
+        assert!(healthy_response.starts_with("HTTP/1.1 200 OK"));
+        assert!(healthy_response.ends_with("Still running"));
+        assert!(panicked_response.starts_with("HTTP/1.1 500 Internal Server Error"));
+        assert!(panicked_response.ends_with(r#"{"error":"Internal server error"}"#));
+        shutdown_sender.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test79_only_authenticated_helpers_can_requeue_groups() {
+        let args = ServerArguments {
+            helper_tokens: vec!["Ivan:secret".to_string(), "Ana:other".to_string()],
+            ..ServerArguments::default()
+        };
+        let help_queue = HelpQueue::new().expect("Error creating the help queue");
+        for group in [1, 2] {
+            help_queue
+                .enqueue(group, 887022804183175188)
+                .await
+                .expect("Error enqueueing help");
+        }
+        help_queue.claim("Ivan").await.expect("Error claiming");
+        let routes = routes_with_queue_test(help_queue.clone(), args);
+
+        let anonymous_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/requeue/1")
+            .reply(&routes)
+            .await;
+        let other_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/requeue/1")
+            .header("authorization", "Bearer other")
+            .reply(&routes)
+            .await;
+        let claimer_response = warp::test::request()
+            .method("POST")
+            .path("/api/discord/v1/requeue/1")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(anonymous_response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(other_response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(claimer_response.status(), StatusCode::OK);
+        assert_eq!(
+            help_queue.sorted().unwrap().collect::<Vec<u16>>(),
+            vec![2, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test80_shutdown_closes_websockets() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let args = ServerArguments {
+            address: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerArguments::default()
+        };
+        let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+        let (address, server) = WebServer::start_server(
+            HelpQueue::new().expect("Error creating the help queue"),
+            AuditLog::new(),
+            &args,
+            Listener::All,
+            args.port,
+            async {
+                let _ = shutdown.await;
+            },
+        )
+        .expect("Error starting the server");
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let mut stream = BufReader::new(stream);
+        stream
+            .write_all(
+                b"GET /api/discord/v1/ws HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut status = String::new();
+        stream.read_line(&mut status).await.unwrap();
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+        }
+        // The queue is sent on connect, as a short unmasked text frame.
+        let mut on_connect = [0; 4];
+        stream.read_exact(&mut on_connect).await.unwrap();
+
+        shutdown_sender.send(()).unwrap();
+        let mut on_shutdown = [0; 2];
+        let closed =
+            tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut on_shutdown)).await;
+
+        assert!(status.starts_with("HTTP/1.1 101 Switching Protocols"));
+        assert_eq!(on_connect, [0x81, 2, b'[', b']']);
+        assert!(closed.is_ok());
+        assert_eq!(on_shutdown[0], 0x88);
+        assert!(tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .is_ok());
     }
 }